@@ -0,0 +1,75 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Decode JPEG XL images from JavaScript.
+//!
+//! Build it for WebAssembly without the thread pool, with a WASI SDK for the vendored libjxl:
+//! ```sh
+//! WASI_SDK_PATH=/opt/wasi-sdk cargo build --release --example wasm \
+//!     --target wasm32-wasip1 --no-default-features --features vendored
+//! ```
+//!
+//! From JavaScript, copy the file into a buffer from `alloc_buffer`, then call
+//! `decode_to_rgba8` with a second 8-byte buffer receiving the width and height. The
+//! returned pixels are `width * height * 4` bytes long, release them and the buffers
+//! with `free_buffer`.
+
+use jpegxl_rs::{decode::PixelFormat, decoder_builder, memory::RustAllocator};
+
+/// Allocate `len` bytes for passing data to the module
+#[no_mangle]
+pub extern "C" fn alloc_buffer(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()).cast()
+}
+
+/// Release a buffer returned by [`alloc_buffer`] or [`decode_to_rgba8`]
+///
+/// # Safety
+/// `ptr` and `len` must describe a buffer returned by this module, which is not used anymore
+#[no_mangle]
+pub unsafe extern "C" fn free_buffer(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+/// Decode the image in `data` to 8-bit RGBA, writing its width and height to `size`.
+/// Return null if decoding fails
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, and `size` to two writable `u32`
+#[no_mangle]
+pub unsafe extern "C" fn decode_to_rgba8(data: *const u8, len: usize, size: *mut u32) -> *mut u8 {
+    let memory_manager = RustAllocator;
+    let Ok(decoder) = decoder_builder()
+        .memory_manager(&memory_manager)
+        .pixel_format(PixelFormat {
+            num_channels: 4,
+            ..PixelFormat::default()
+        })
+        .build()
+    else {
+        return std::ptr::null_mut();
+    };
+
+    match decoder.decode_with::<u8>(std::slice::from_raw_parts(data, len)) {
+        Ok((metadata, pixels)) => {
+            size.write(metadata.width);
+            size.add(1).write(metadata.height);
+            Box::into_raw(pixels.into_boxed_slice()).cast()
+        }
+        Err(_) => std::ptr::null_mut(),
+    }
+}
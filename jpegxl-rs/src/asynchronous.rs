@@ -0,0 +1,468 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `tokio` integration
+//!
+//! Decoders and encoders are not [`Send`] and libjxl blocks while processing, so the
+//! work is done on the blocking thread pool of the runtime, and only the IO happens
+//! on the async tasks. Input and output cross over in chunks through bounded channels,
+//! so neither is held in memory as a whole. Frames of animations can be consumed as a
+//! [`Stream`] with [`frame_stream`].
+//!
+//! # Example
+//! ```
+//! # async fn f(input: &[u8]) -> Result<(), jpegxl_rs::asynchronous::AsyncError> {
+//! use jpegxl_rs::{asynchronous::decode_with, decoder_builder};
+//!
+//! let (metadata, pixels) = decode_with(input, |reader| {
+//!     decoder_builder().build()?.decode_reader_with::<u8>(reader)
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    io::{self, Read, Write},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_core::Stream;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::{spawn_blocking, JoinError, JoinHandle},
+};
+
+use crate::{
+    common::PixelType,
+    decode::{Frame, JxlDecoder, Metadata, Pixels},
+    decoder_builder, DecodeError, EncodeError,
+};
+
+/// Errors of the async wrappers
+#[derive(Error, Debug)]
+pub enum AsyncError {
+    /// Reading the input or writing the output failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Decoding failed
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    /// Encoding failed
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+    /// The blocking task panicked or was cancelled
+    #[error("Blocking task failed: {0}")]
+    Join(#[from] JoinError),
+}
+
+impl From<crate::Error> for AsyncError {
+    fn from(e: crate::Error) -> Self {
+        match e {
+            crate::Error::Decode(e) => Self::Decode(e),
+            crate::Error::Encode(e) => Self::Encode(e),
+        }
+    }
+}
+
+/// Size of the chunks read from the input, as the decoder takes them
+const INPUT_CHUNK: usize = 64 * 1024;
+
+/// Decode the input with a default decoder, see [`decode_with`]
+///
+/// # Errors
+/// Return an [`AsyncError`] when reading or decoding fails
+pub async fn decode<R: AsyncRead + Unpin>(reader: R) -> Result<(Metadata, Pixels), AsyncError> {
+    decode_with(reader, |input| {
+        decoder_builder().build()?.decode_reader(input)
+    })
+    .await
+}
+
+/// Run `decode` in a blocking thread, on the input read from `reader` in chunks
+///
+/// The decoder should be built inside `decode`, since it cannot be sent across threads.
+/// Passing the [`ChunkReader`] to [`JxlDecoder::decode_reader`] decodes the chunks as they
+/// arrive, while reading it to the end first buffers the whole input.
+///
+/// # Errors
+/// Return an [`AsyncError`] when reading fails or `decode` returns an error
+pub async fn decode_with<R, F, T>(mut reader: R, decode: F) -> Result<T, AsyncError>
+where
+    R: AsyncRead + Unpin,
+    F: FnOnce(ChunkReader) -> Result<T, DecodeError> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, chunks) = mpsc::channel(2);
+    let task = spawn_blocking(move || {
+        decode(ChunkReader {
+            chunks,
+            chunk: vec![],
+            pos: 0,
+        })
+    });
+
+    let mut read_error = None;
+    loop {
+        let mut chunk = Vec::with_capacity(INPUT_CHUNK);
+        match (&mut reader)
+            .take(INPUT_CHUNK as u64)
+            .read_to_end(&mut chunk)
+            .await
+        {
+            Ok(0) => break,
+            // The decoding stopped without taking all the input
+            Ok(_) => {
+                if sender.send(Ok(chunk)).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                _ = sender.send(Err(e.kind().into())).await;
+                read_error = Some(e);
+                break;
+            }
+        }
+    }
+    drop(sender);
+
+    let result = task.await?;
+    if let Some(e) = read_error {
+        return Err(e.into());
+    }
+    Ok(result?)
+}
+
+/// Input of [`decode_with`], receiving the chunks read by the async task
+///
+/// Reading blocks until the next chunk arrives, so it is only meant for the blocking
+/// thread running the decoder.
+pub struct ChunkReader {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Run `decode` on a buffer already in memory in a blocking thread.
+///
+/// Any owned buffer is accepted, so a reference-counted `bytes::Bytes` from a network
+/// stack is passed on without copying.
+///
+/// # Errors
+/// Return an [`AsyncError`] when `decode` returns an error
+pub async fn decode_buffer<D, F, T>(data: D, decode: F) -> Result<T, AsyncError>
+where
+    D: AsRef<[u8]> + Send + 'static,
+    F: FnOnce(&[u8]) -> Result<T, DecodeError> + Send + 'static,
+    T: Send + 'static,
+{
+    Ok(spawn_blocking(move || decode(data.as_ref())).await??)
+}
+
+/// Run `encode` in a blocking thread, writing its output to `writer` as it is produced
+///
+/// The encoder should be built inside `encode`, since it cannot be sent across threads.
+/// Passing the [`ChunkWriter`] to
+/// [`MultiFrames::encode_to`](crate::encode::MultiFrames::encode_to) hands over each chunk as soon
+/// as it is compressed.
+///
+/// # Errors
+/// Return an [`AsyncError`] when `encode` returns an error or writing fails
+pub async fn encode_to<W, F>(mut writer: W, encode: F) -> Result<(), AsyncError>
+where
+    W: AsyncWrite + Unpin,
+    F: FnOnce(ChunkWriter) -> Result<(), EncodeError> + Send + 'static,
+{
+    let (sender, mut chunks) = mpsc::channel(2);
+    let task = spawn_blocking(move || encode(ChunkWriter(sender)));
+
+    let mut write_error = None;
+    while let Some(chunk) = chunks.recv().await {
+        if let Err(e) = writer.write_all(&chunk).await {
+            write_error = Some(e);
+            break;
+        }
+    }
+    // Fail the next write of the encoder
+    drop(chunks);
+
+    let result = task.await?;
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    result?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Output of [`encode_to`], sending the chunks written to the async task
+///
+/// Writing blocks while the async task is behind, so it is only meant for the blocking
+/// thread running the encoder.
+pub struct ChunkWriter(mpsc::Sender<Vec<u8>>);
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Stream of decoded frames, created by [`frame_stream`]
+///
+/// Frames are decoded on a blocking thread, which waits until the previous frame is
+/// taken before decoding the next one. Dropping the stream stops the decoding.
+pub struct FrameStream<T> {
+    frames: mpsc::Receiver<Result<Frame<T>, DecodeError>>,
+    task: Option<JoinHandle<()>>,
+}
+
+/// Decode the frames of `data` with a default decoder, see [`frame_stream_with`]
+///
+/// # Panics
+/// Panic if called outside of a tokio runtime
+#[must_use]
+pub fn frame_stream<T, D>(data: D) -> FrameStream<T>
+where
+    T: PixelType + Send + 'static,
+    D: AsRef<[u8]> + Send + 'static,
+{
+    frame_stream_with(data, || decoder_builder().build())
+}
+
+/// Decode the frames of `data` with the decoder returned by `build`, in pixels of type `T`
+///
+/// The decoder is built on the blocking thread, since it cannot be sent across threads.
+/// Like [`decode_buffer`], `data` can be any owned buffer.
+///
+/// # Panics
+/// Panic if called outside of a tokio runtime
+#[must_use]
+pub fn frame_stream_with<T, D, F>(data: D, build: F) -> FrameStream<T>
+where
+    T: PixelType + Send + 'static,
+    D: AsRef<[u8]> + Send + 'static,
+    F: FnOnce() -> Result<JxlDecoder<'static, 'static>, DecodeError> + Send + 'static,
+{
+    let (sender, frames) = mpsc::channel(1);
+    let task = spawn_blocking(move || {
+        let mut decoder = match build() {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                _ = sender.blocking_send(Err(e));
+                return;
+            }
+        };
+
+        let frames = decoder.frames::<T>(data.as_ref());
+        match frames {
+            Ok(frames) => {
+                for frame in frames {
+                    // The stream was dropped
+                    if sender.blocking_send(frame).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(e) => _ = sender.blocking_send(Err(e)),
+        }
+    });
+
+    FrameStream {
+        frames,
+        task: Some(task),
+    }
+}
+
+impl<T> Stream for FrameStream<T> {
+    type Item = Result<Frame<T>, AsyncError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(frame) = ready!(this.frames.poll_recv(cx)) {
+            return Poll::Ready(Some(frame.map_err(AsyncError::from)));
+        }
+
+        // Report a panic of the decoding thread, instead of ending silently
+        let Some(task) = this.task.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let result = ready!(Pin::new(task).poll(cx));
+        this.task = None;
+        Poll::Ready(result.err().map(|e| Err(e.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encoder_builder, tests::SAMPLE_JXL};
+
+    use testresult::TestResult;
+
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn roundtrip() -> TestResult {
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+
+        runtime.block_on(async {
+            let (metadata, pixels) = decode(SAMPLE_JXL).await?;
+            let Pixels::Uint16(pixels) = pixels else {
+                panic!("Unexpected pixel type");
+            };
+            let (width, height) = (metadata.width, metadata.height);
+
+            let mut output = vec![];
+            encode_to(&mut output, move |writer| {
+                encoder_builder()
+                    .has_alpha(true)
+                    .init_buffer_size(64)
+                    .build()?
+                    .multiple::<u8>(width, height)?
+                    .add_frame(&crate::encode::EncoderFrame::new(&pixels).num_channels(4))?
+                    .encode_to(writer)
+            })
+            .await?;
+
+            let (metadata, _) = decode_with(&output[..], |reader| {
+                decoder_builder().build()?.decode_reader_with::<u8>(reader)
+            })
+            .await?;
+            assert_eq!((metadata.width, metadata.height), (width, height));
+
+            // Input spanning several chunks
+            let mut large = vec![];
+            let noise: Vec<u8> = std::iter::successors(Some(1u32), |x| {
+                let x = x ^ (x << 13);
+                let x = x ^ (x >> 17);
+                Some(x ^ (x << 5))
+            })
+            .take(256 * 256 * 3)
+            .map(|x| x.to_le_bytes()[0])
+            .collect();
+            encode_to(&mut large, move |writer| {
+                encoder_builder()
+                    .uses_original_profile(true)
+                    .lossless(true)
+                    .build()?
+                    .multiple::<u8>(256, 256)?
+                    .add_frame(&crate::encode::EncoderFrame::new(&noise))?
+                    .encode_to(writer)
+            })
+            .await?;
+            assert!(large.len() > 2 * INPUT_CHUNK);
+            let (metadata, _) = decode(&large[..]).await?;
+            assert_eq!((metadata.width, metadata.height), (256, 256));
+
+            // Truncated or failing input
+            assert!(decode(&large[..large.len() / 2]).await.is_err());
+            let failing = tokio::io::AsyncReadExt::chain(&large[..INPUT_CHUNK], FailingReader);
+            assert!(matches!(decode(failing).await, Err(AsyncError::Io(_))));
+
+            let shared = bytes::Bytes::from(output);
+            let (metadata, _) = decode_buffer(shared.clone(), |data| {
+                decoder_builder().build()?.decode(data)
+            })
+            .await?;
+            assert_eq!((metadata.width, metadata.height), (width, height));
+
+            assert!(matches!(
+                decode(&[0u8; 4][..]).await,
+                Err(AsyncError::Decode(DecodeError::InvalidInput))
+            ));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn frames() -> TestResult {
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        let pixels = vec![0u8; 4 * 4 * 3];
+        let frame = crate::encode::EncoderFrame::new(&pixels);
+        let data = encoder_builder()
+            .build()?
+            .multiple::<u8>(4, 4)?
+            .add_frame(&frame)?
+            .add_frame(&frame)?
+            .encode()?
+            .data;
+
+        runtime.block_on(async {
+            let mut stream =
+                frame_stream_with::<u8, _, _>(data, || decoder_builder().coalescing(false).build());
+            let mut count = 0;
+            while let Some(frame) =
+                std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await
+            {
+                assert_eq!(frame?.pixels.len(), pixels.len());
+                count += 1;
+            }
+            assert_eq!(count, 2);
+
+            let mut stream = frame_stream::<u8, _>(vec![0; 4]);
+            assert!(matches!(
+                std::future::poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await,
+                Some(Err(AsyncError::Decode(DecodeError::InvalidInput)))
+            ));
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,182 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! C API for the simple decoding and encoding paths, with the `capi` feature
+//!
+//! Build a shared library with `cargo rustc --release --features capi --crate-type cdylib`.
+//! All functions return null on failure, and the returned buffers must be released with
+//! [`jxlrs_free`].
+//!
+//! ```c
+//! uint32_t width, height;
+//! size_t len;
+//! uint8_t *pixels = jxlrs_decode_rgba8(data, data_len, &width, &height, &len);
+//! if (pixels) {
+//!     /* use width * height RGBA pixels */
+//!     jxlrs_free(pixels, len);
+//! }
+//! ```
+
+use std::{panic::catch_unwind, ptr::null_mut, slice};
+
+use crate::{decode::PixelFormat, decoder_builder, encode::EncoderFrame, encoder_builder};
+
+fn into_raw(buffer: Vec<u8>, out_len: *mut usize) -> *mut u8 {
+    unsafe { out_len.write(buffer.len()) };
+    Box::into_raw(buffer.into_boxed_slice()).cast()
+}
+
+/// Decode a JPEG XL image to 8-bit RGBA pixels
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, and the other pointers must be writable
+#[no_mangle]
+pub unsafe extern "C" fn jxlrs_decode_rgba8(
+    data: *const u8,
+    len: usize,
+    width: *mut u32,
+    height: *mut u32,
+    out_len: *mut usize,
+) -> *mut u8 {
+    if data.is_null() || width.is_null() || height.is_null() || out_len.is_null() {
+        return null_mut();
+    }
+    let data = slice::from_raw_parts(data, len);
+
+    catch_unwind(|| {
+        let decoder = decoder_builder()
+            .pixel_format(PixelFormat {
+                num_channels: 4,
+                ..PixelFormat::default()
+            })
+            .build()
+            .ok()?;
+        let (metadata, pixels) = decoder.decode_with::<u8>(data).ok()?;
+        width.write(metadata.width);
+        height.write(metadata.height);
+        Some(into_raw(pixels, out_len))
+    })
+    .ok()
+    .flatten()
+    .unwrap_or(null_mut())
+}
+
+/// Encode 8-bit RGBA pixels to JPEG XL, with the butteraugli `distance` or losslessly
+/// if it is 0
+///
+/// # Safety
+/// `pixels` must point to `width * height * 4` readable bytes, and `out_len` must be writable
+#[no_mangle]
+pub unsafe extern "C" fn jxlrs_encode_rgba8(
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    distance: f32,
+    out_len: *mut usize,
+) -> *mut u8 {
+    let Some(len) = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(4))
+    else {
+        return null_mut();
+    };
+    if pixels.is_null() || out_len.is_null() {
+        return null_mut();
+    }
+    let pixels = slice::from_raw_parts(pixels, len);
+
+    catch_unwind(|| {
+        let mut encoder = encoder_builder()
+            .has_alpha(true)
+            .lossless(distance == 0.0)
+            .uses_original_profile(distance == 0.0)
+            .quality(distance)
+            .build()
+            .ok()?;
+        let result = encoder
+            .encode_frame::<u8, u8>(&EncoderFrame::new(pixels).num_channels(4), width, height)
+            .ok()?;
+        Some(into_raw(result.data, out_len))
+    })
+    .ok()
+    .flatten()
+    .unwrap_or(null_mut())
+}
+
+/// Release a buffer returned by this library
+///
+/// # Safety
+/// `ptr` and `len` must come from a previous call, and the buffer must not be used anymore
+#[no_mangle]
+pub unsafe extern "C" fn jxlrs_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::SAMPLE_JXL;
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_roundtrip() {
+        let (mut width, mut height, mut len) = (0, 0, 0);
+        let pixels = unsafe {
+            jxlrs_decode_rgba8(
+                SAMPLE_JXL.as_ptr(),
+                SAMPLE_JXL.len(),
+                &mut width,
+                &mut height,
+                &mut len,
+            )
+        };
+        assert!(!pixels.is_null());
+        assert_eq!((width, height, len), (40, 50, 40 * 50 * 4));
+
+        let mut encoded_len = 0;
+        let encoded = unsafe { jxlrs_encode_rgba8(pixels, width, height, 0.0, &mut encoded_len) };
+        assert!(!encoded.is_null());
+
+        let (mut width, mut height, mut decoded_len) = (0, 0, 0);
+        let decoded = unsafe {
+            jxlrs_decode_rgba8(
+                encoded,
+                encoded_len,
+                &mut width,
+                &mut height,
+                &mut decoded_len,
+            )
+        };
+        assert!(!decoded.is_null());
+        assert_eq!(
+            unsafe { slice::from_raw_parts(decoded, decoded_len) },
+            unsafe { slice::from_raw_parts(pixels, len) },
+        );
+
+        unsafe {
+            jxlrs_free(pixels, len);
+            jxlrs_free(encoded, encoded_len);
+            jxlrs_free(decoded, decoded_len);
+            assert!(
+                jxlrs_decode_rgba8([0u8; 4].as_ptr(), 4, &mut width, &mut height, &mut len)
+                    .is_null()
+            );
+        }
+    }
+}
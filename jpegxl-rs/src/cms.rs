@@ -0,0 +1,393 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Color management interface, to convert colors with another engine than libjxl's
+//!
+//! libjxl uses a color management system when encoding images which are not in sRGB, and
+//! when decoding to another color profile than the one of the image. Set a
+//! [`ColorManagement`] implementation in the `cms` option of the decoder or encoder to
+//! replace the built-in one. With the `lcms2` feature, [`Lcms2`] is provided.
+
+use std::{
+    ffi::c_void,
+    mem::MaybeUninit,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::null_mut,
+    slice,
+    sync::Arc,
+};
+
+use jpegxl_sys::{
+    cms::{JxlCmsInterface, JxlColorProfile, JxlGetDefaultCms},
+    color_encoding::JxlColorEncoding,
+    types::JxlBool,
+};
+
+/// Color profile of the pixels given to or expected from a [`ColorTransform`]
+#[derive(Clone, Copy, Debug)]
+pub struct CmsProfile<'a> {
+    /// ICC profile
+    pub icc: &'a [u8],
+    /// Equivalent color encoding, when the profile can be represented as one
+    pub color_encoding: &'a JxlColorEncoding,
+    /// Number of channels of the pixels, 1 for gray, 3 for RGB or 4 for CMYK
+    pub num_channels: usize,
+}
+
+/// Conversion between two color profiles
+pub trait ColorTransform: Send + Sync {
+    /// Convert `input` pixels to `output`, interleaved in the channels of their profiles,
+    /// with samples nominally in 0 to 1.
+    /// Return `false` if the conversion fails. Called from the threads of the parallel runner
+    fn run(&self, input: &[f32], output: &mut [f32]) -> bool;
+}
+
+/// Color management system
+pub trait ColorManagement: Send + Sync {
+    /// Parse an ICC profile into a color encoding, and whether it is a CMYK profile.
+    /// Return `None` if the profile is invalid.
+    ///
+    /// Defaults to the parser of libjxl, which only depends on the profile
+    fn color_encoding_from_icc(&self, icc: &[u8]) -> Option<(JxlColorEncoding, bool)> {
+        let default = unsafe { &*JxlGetDefaultCms() };
+        let mut color_encoding = MaybeUninit::uninit();
+        let mut cmyk = JxlBool::False;
+        let ok = (default.set_fields_from_icc)(
+            default.set_fields_data,
+            icc.as_ptr(),
+            icc.len(),
+            color_encoding.as_mut_ptr(),
+            &mut cmyk,
+        );
+        (ok == JxlBool::True).then(|| {
+            (
+                unsafe { color_encoding.assume_init() },
+                cmyk == JxlBool::True,
+            )
+        })
+    }
+
+    /// Create a transform from `input` to `output`. Return `None` if it is not supported.
+    ///
+    /// # Note
+    /// libjxl 0.10 aborts the process if a transform needed for encoding is not supported,
+    /// instead of returning an error
+    ///
+    /// `intensity_target` is the peak luminance in nits, for HDR transfer functions
+    fn transform(
+        &self,
+        input: CmsProfile<'_>,
+        output: CmsProfile<'_>,
+        intensity_target: f32,
+    ) -> Option<Box<dyn ColorTransform>>;
+}
+
+/// Transform with the buffers handed out to libjxl
+struct CmsTransform {
+    transform: Box<dyn ColorTransform>,
+    in_channels: usize,
+    out_channels: usize,
+    // Per thread, source and destination buffers, then a copy of the input for
+    // conversions in place. Only accessed by libjxl and `run` through the pointers
+    _buffers: Vec<[Vec<f32>; 3]>,
+    pointers: Vec<Vec<*mut f32>>,
+}
+
+/// Build the interface given to libjxl, calling `cms`.
+///
+/// `cms` must outlive the use of the interface by the decoder or encoder, and stay at the
+/// same address meanwhile
+pub(crate) fn interface(cms: &Arc<dyn ColorManagement>) -> JxlCmsInterface {
+    let data = (cms as *const Arc<dyn ColorManagement>).cast_mut().cast();
+    JxlCmsInterface {
+        set_fields_data: data,
+        set_fields_from_icc,
+        init_data: data,
+        init,
+        get_src_buf,
+        get_dst_buf,
+        run,
+        destroy,
+    }
+}
+
+// The callbacks can not unwind into libjxl, so panics are reported as failures
+
+extern "C" fn set_fields_from_icc(
+    user_data: *mut c_void,
+    icc_data: *const u8,
+    icc_size: usize,
+    c: *mut JxlColorEncoding,
+    cmyk: *mut JxlBool,
+) -> JxlBool {
+    catch_unwind(|| {
+        let cms = unsafe { &*user_data.cast::<Arc<dyn ColorManagement>>() };
+        let icc = unsafe { slice::from_raw_parts(icc_data, icc_size) };
+        let (color_encoding, is_cmyk) = cms.color_encoding_from_icc(icc)?;
+        unsafe {
+            c.write(color_encoding);
+            cmyk.write(is_cmyk.into());
+        }
+        Some(())
+    })
+    .ok()
+    .flatten()
+    .is_some()
+    .into()
+}
+
+unsafe fn profile<'a>(profile: *const JxlColorProfile) -> CmsProfile<'a> {
+    let profile = &*profile;
+    CmsProfile {
+        icc: if profile.icc.data.is_null() {
+            &[]
+        } else {
+            slice::from_raw_parts(profile.icc.data, profile.icc.size)
+        },
+        color_encoding: &profile.color_encoding,
+        num_channels: profile.num_channels,
+    }
+}
+
+extern "C" fn init(
+    init_data: *mut c_void,
+    num_threads: usize,
+    pixels_per_thread: usize,
+    input_profile: *const JxlColorProfile,
+    output_profile: *const JxlColorProfile,
+    intensity_target: f32,
+) -> *mut c_void {
+    catch_unwind(|| {
+        let cms = unsafe { &*init_data.cast::<Arc<dyn ColorManagement>>() };
+        let (input, output) = unsafe { (profile(input_profile), profile(output_profile)) };
+        let transform = cms.transform(input, output, intensity_target)?;
+
+        let (in_channels, out_channels) = (input.num_channels, output.num_channels);
+        let mut buffers: Vec<_> = (0..num_threads)
+            .map(|_| {
+                [in_channels, out_channels, in_channels]
+                    .map(|channels| vec![0.0; pixels_per_thread * channels])
+            })
+            .collect();
+        let pointers = buffers
+            .iter_mut()
+            .map(|bufs| bufs.iter_mut().map(Vec::as_mut_ptr).collect())
+            .collect();
+        let state = CmsTransform {
+            transform,
+            in_channels,
+            out_channels,
+            _buffers: buffers,
+            pointers,
+        };
+        Some(Box::into_raw(Box::new(state)).cast())
+    })
+    .ok()
+    .flatten()
+    .unwrap_or(null_mut())
+}
+
+extern "C" fn get_src_buf(user_data: *mut c_void, thread: usize) -> *mut f32 {
+    let state = unsafe { &*user_data.cast::<CmsTransform>() };
+    state.pointers[thread][0]
+}
+
+extern "C" fn get_dst_buf(user_data: *mut c_void, thread: usize) -> *mut f32 {
+    let state = unsafe { &*user_data.cast::<CmsTransform>() };
+    state.pointers[thread][1]
+}
+
+extern "C" fn run(
+    user_data: *mut c_void,
+    thread: usize,
+    input_buffer: *const f32,
+    output_buffer: *mut f32,
+    num_pixels: usize,
+) -> JxlBool {
+    catch_unwind(AssertUnwindSafe(|| {
+        let state = unsafe { &*user_data.cast::<CmsTransform>() };
+        let in_len = num_pixels * state.in_channels;
+        let out_len = num_pixels * state.out_channels;
+
+        let mut input = input_buffer;
+        let overlap = (input_buffer as usize) < (output_buffer as usize + out_len * 4)
+            && (output_buffer as usize) < (input_buffer as usize + in_len * 4);
+        if overlap {
+            // Converting in place, so keep the input aside
+            let copy = state.pointers[thread][2];
+            unsafe { copy.copy_from(input_buffer, in_len) };
+            input = copy;
+        }
+
+        let (input, output) = unsafe {
+            (
+                slice::from_raw_parts(input, in_len),
+                slice::from_raw_parts_mut(output_buffer, out_len),
+            )
+        };
+        state.transform.run(input, output)
+    }))
+    .unwrap_or(false)
+    .into()
+}
+
+extern "C" fn destroy(user_data: *mut c_void) {
+    if !user_data.is_null() {
+        drop(unsafe { Box::from_raw(user_data.cast::<CmsTransform>()) });
+    }
+}
+
+#[cfg(feature = "lcms2")]
+mod lcms;
+#[cfg(feature = "lcms2")]
+pub use lcms::Lcms2;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        decoder_builder,
+        encode::{ColorEncoding, EncoderResult},
+        encoder_builder,
+    };
+
+    /// Copy the channels, which is only right for equivalent profiles
+    #[derive(Default)]
+    struct Passthrough;
+
+    impl ColorManagement for Passthrough {
+        fn transform(
+            &self,
+            input: CmsProfile<'_>,
+            output: CmsProfile<'_>,
+            _intensity_target: f32,
+        ) -> Option<Box<dyn ColorTransform>> {
+            (input.num_channels == output.num_channels).then(|| Box::new(Self) as _)
+        }
+    }
+
+    impl ColorTransform for Passthrough {
+        fn run(&self, input: &[f32], output: &mut [f32]) -> bool {
+            output.copy_from_slice(input);
+            true
+        }
+    }
+
+    #[derive(Default)]
+    struct Counting<C> {
+        cms: C,
+        transforms: AtomicUsize,
+    }
+
+    impl<C: ColorManagement> ColorManagement for Counting<C> {
+        fn transform(
+            &self,
+            input: CmsProfile<'_>,
+            output: CmsProfile<'_>,
+            intensity_target: f32,
+        ) -> Option<Box<dyn ColorTransform>> {
+            self.transforms.fetch_add(1, Ordering::Relaxed);
+            self.cms.transform(input, output, intensity_target)
+        }
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_custom() -> TestResult {
+        let cms = Arc::new(Counting::<Passthrough>::default());
+        let data = vec![128u8; 3 * 8 * 8];
+        let mut encoder = encoder_builder()
+            .color_encoding(ColorEncoding::LinearProPhoto)
+            .cms(cms.clone())
+            .build()?;
+
+        // Converting to XYB needs the color management system
+        let result: EncoderResult<u8> = encoder.encode(&data, 8, 8)?;
+        assert!(cms.transforms.load(Ordering::Relaxed) > 0);
+        decoder_builder().build()?.decode(&result)?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_encoder_cms_changed() -> TestResult {
+        let cms = Arc::new(Counting::<Passthrough>::default());
+        let data = vec![128u8; 3 * 8 * 8];
+        let encoder = encoder_builder()
+            .color_encoding(ColorEncoding::LinearProPhoto)
+            .cms(cms.clone())
+            .build()?;
+
+        // The interface stays valid when the encoder is moved
+        let mut encoder = Box::new(encoder);
+        let _: EncoderResult<u8> = encoder.encode(&data, 8, 8)?;
+        let transforms = cms.transforms.load(Ordering::Relaxed);
+        assert!(transforms > 0);
+
+        // Without a system set, the one of libjxl is installed again
+        encoder.cms = None;
+        let _: EncoderResult<u8> = encoder.encode(&data, 8, 8)?;
+        assert_eq!(cms.transforms.load(Ordering::Relaxed), transforms);
+        assert_eq!(Arc::strong_count(&cms), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_srgb8() -> TestResult {
+        let cms = Arc::new(Counting::<Passthrough>::default());
+        let data = vec![128u8; 3 * 8 * 8];
+        let mut encoder = encoder_builder()
+            .color_encoding(ColorEncoding::ProPhoto)
+            .build()?;
+        let result: EncoderResult<u8> = encoder.encode(&data, 8, 8)?;
+
+        // The XYB pixels are converted to linear sRGB first, then to sRGB by `cms`
+        let (rgb, _, _) =
+            crate::decode::decode_to_srgb8_with_cms(&result, [255; 3], Some(cms.clone()))?;
+        assert_eq!(rgb.len(), data.len());
+        assert!(cms.transforms.load(Ordering::Relaxed) > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "lcms2")]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_lcms2() -> TestResult {
+        let cms = Arc::new(Counting::<Lcms2>::default());
+        let data: Vec<u8> = (0..=255).cycle().step_by(3).take(3 * 8 * 8).collect();
+        let mut encoder = encoder_builder()
+            .color_encoding(ColorEncoding::ProPhoto)
+            .cms(cms.clone())
+            .build()?;
+        let result: EncoderResult<u8> = encoder.encode(&data, 8, 8)?;
+        assert!(cms.transforms.load(Ordering::Relaxed) > 0);
+
+        let decoder = decoder_builder().cms(cms).build()?;
+        let (_, pixels) = decoder.decode_with::<u8>(&result)?;
+        assert_eq!(pixels.len(), data.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use jpegxl_sys::color_encoding::JxlRenderingIntent;
+use lcms2::{DisallowCache, Flags, GlobalContext, Intent, PixelFormat, Profile, Transform};
+
+use super::{CmsProfile, ColorManagement, ColorTransform};
+
+/// Color management system backed by Little CMS
+///
+/// Only gray and RGB profiles are supported
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lcms2;
+
+impl ColorManagement for Lcms2 {
+    fn transform(
+        &self,
+        input: CmsProfile<'_>,
+        output: CmsProfile<'_>,
+        _intensity_target: f32,
+    ) -> Option<Box<dyn ColorTransform>> {
+        let format = |channels| match channels {
+            1 => Some(PixelFormat::GRAY_FLT),
+            3 => Some(PixelFormat::RGB_FLT),
+            _ => None,
+        };
+        let intent = match output.color_encoding.rendering_intent {
+            JxlRenderingIntent::Perceptual => Intent::Perceptual,
+            JxlRenderingIntent::Relative => Intent::RelativeColorimetric,
+            JxlRenderingIntent::Saturation => Intent::Saturation,
+            JxlRenderingIntent::Absolute => Intent::AbsoluteColorimetric,
+        };
+
+        let transform = Transform::new_flags_context(
+            GlobalContext::new(),
+            &Profile::new_icc(input.icc).ok()?,
+            format(input.num_channels)?,
+            &Profile::new_icc(output.icc).ok()?,
+            format(output.num_channels)?,
+            intent,
+            Flags::NO_CACHE,
+        )
+        .ok()?;
+        Some(Box::new(Lcms2Transform(transform)))
+    }
+}
+
+struct Lcms2Transform(Transform<u8, u8, GlobalContext, DisallowCache>);
+
+impl ColorTransform for Lcms2Transform {
+    fn run(&self, input: &[f32], output: &mut [f32]) -> bool {
+        // Byte slices are accepted for any pixel format
+        let (input, output) = unsafe {
+            (
+                std::slice::from_raw_parts(input.as_ptr().cast::<u8>(), input.len() * 4),
+                std::slice::from_raw_parts_mut(output.as_mut_ptr().cast::<u8>(), output.len() * 4),
+            )
+        };
+        self.0.transform_pixels(input, output);
+        true
+    }
+}
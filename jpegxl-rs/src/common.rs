@@ -17,6 +17,11 @@ along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 
 //! Common types used across the crate
 
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
 use byteorder::{ByteOrder, NativeEndian, BE, LE};
 use half::f16;
 
@@ -24,6 +29,190 @@ use jpegxl_sys::types::{JxlDataType, JxlPixelFormat};
 
 /// Endianness of the pixels
 pub type Endianness = jpegxl_sys::types::JxlEndianness;
+/// Data type of a sample
+pub type DataType = JxlDataType;
+
+/// Token to cancel a running decoding or encoding from another thread
+///
+/// Clones share the same state. The decoder and encoder check it before every call into
+/// libjxl, so cancelling takes effect at the next event, e.g. between frames, and not in
+/// the middle of one.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token, not cancelled yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation of the operations using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether cancellation was requested
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clear the cancellation, to reuse the token
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Rounding of [`quantize_f32`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dithering {
+    /// Round to the nearest value
+    #[default]
+    None,
+    /// Add triangular noise of one step before rounding, to avoid banding in smooth
+    /// gradients. The noise only depends on the position of the sample, so the output is
+    /// reproducible
+    Noise,
+}
+
+/// Convert integer samples of `bit_depth` bits, e.g. 12 for raw sensor data stored in `u16`,
+/// to floats from 0 to 1
+///
+/// Samples above the maximum of `bit_depth` are not clamped, and map above 1.
+///
+/// # Panics
+/// Panics if `bit_depth` is not between 1 and 16
+#[must_use]
+pub fn normalize_u16(samples: &[u16], bit_depth: u32) -> Vec<f32> {
+    let scale = 1.0 / f32::from(max_value(bit_depth));
+    samples.iter().map(|&v| f32::from(v) * scale).collect()
+}
+
+/// Convert float samples from 0 to 1 to integers of `bit_depth` bits, the reverse of
+/// [`normalize_u16`]
+///
+/// Samples out of range are clamped, and NaN maps to 0.
+///
+/// # Panics
+/// Panics if `bit_depth` is not between 1 and 16
+#[must_use]
+pub fn quantize_f32(samples: &[f32], bit_depth: u32, dithering: Dithering) -> Vec<u16> {
+    let max = f32::from(max_value(bit_depth));
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| {
+            let noise = match dithering {
+                Dithering::None => 0.0,
+                Dithering::Noise => triangular_noise(i),
+            };
+            // Clamped to the range of `u16`, so the cast is exact
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let v = (v.clamp(0.0, 1.0) * max + noise).round().clamp(0.0, max) as u16;
+            v
+        })
+        .collect()
+}
+
+fn max_value(bit_depth: u32) -> u16 {
+    assert!(
+        (1..=16).contains(&bit_depth),
+        "bit depth {bit_depth} is not between 1 and 16"
+    );
+    u16::MAX >> (16 - bit_depth)
+}
+
+/// Noise from -1 to 1, with a triangular distribution, hashed from `index`
+#[allow(clippy::cast_precision_loss)]
+fn triangular_noise(index: usize) -> f32 {
+    // SplitMix64 finalizer
+    let mut x = (index as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    // Sum of two uniform values from 0 to 1, from both halves of the hash
+    let uniform = |bits: u64| (bits & 0xFF_FFFF) as f32 / 16_777_216.0;
+    uniform(x) + uniform(x >> 32) - 1.0
+}
+
+/// Pixel format of the buffers given to the encoder or returned by the decoder
+#[derive(Clone, Copy, Debug)]
+pub struct PixelFormat {
+    /// Amount of channels available in a pixel buffer.
+    ///
+    /// 1. single-channel data, e.g. grayscale or a single extra channel
+    /// 2. single-channel + alpha
+    /// 3. trichromatic, e.g. RGB
+    /// 4. trichromatic + alpha
+    ///
+    /// # Default
+    /// 0, which means determined automatically from color channels and alpha bits when
+    /// decoding, and 3 when encoding
+    pub num_channels: u32,
+    /// Data type of the samples.
+    ///
+    /// Methods generic over the pixel type, like
+    /// [`JxlDecoder::decode_with`](crate::decode::JxlDecoder::decode_with), always use
+    /// that type instead.
+    ///
+    /// # Default
+    /// `None`, which means determined by the bit depth of the image when decoding
+    pub data_type: Option<DataType>,
+    /// Whether multibyte data types are represented in big endian or little
+    /// endian format. This applies to `u16`, `f16`, and `f32`.
+    ///
+    /// Typed samples are always returned in native order, use
+    /// [`JxlDecoder::decode_bytes_with`](crate::decode::JxlDecoder::decode_bytes_with)
+    /// to get the bytes in this order.
+    ///
+    /// # Default
+    /// [`Endianness::Native`]
+    pub endianness: Endianness,
+    /// Align scanlines to a multiple of align bytes.
+    ///
+    /// # Default
+    /// 0, which means requiring no alignment (which has the same effect as value 1)
+    pub align: usize,
+}
+
+impl Default for PixelFormat {
+    fn default() -> Self {
+        Self {
+            num_channels: 0,
+            data_type: None,
+            endianness: Endianness::Native,
+            align: 0,
+        }
+    }
+}
+
+impl PixelFormat {
+    /// Fill in the automatic channel count, with the resolved data type
+    pub(crate) fn to_jxl(self, num_channels: u32, data_type: DataType) -> JxlPixelFormat {
+        JxlPixelFormat {
+            num_channels: if self.num_channels == 0 {
+                num_channels
+            } else {
+                self.num_channels
+            },
+            data_type,
+            endianness: self.endianness,
+            align: self.align,
+        }
+    }
+}
+
+impl From<JxlPixelFormat> for PixelFormat {
+    fn from(f: JxlPixelFormat) -> Self {
+        Self {
+            num_channels: f.num_channels,
+            data_type: Some(f.data_type),
+            endianness: f.endianness,
+            align: f.align,
+        }
+    }
+}
 
 mod private {
     pub trait Sealed {}
@@ -123,3 +312,40 @@ impl PixelType for f16 {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_normalization() {
+        let samples = [0, 1, 2048, 4095];
+        let normalized = normalize_u16(&samples, 12);
+        assert!((normalized[3] - 1.0).abs() < f32::EPSILON);
+        assert_eq!(quantize_f32(&normalized, 12, Dithering::None), samples);
+        assert_eq!(
+            quantize_f32(&[-1.0, 2.0, f32::NAN], 8, Dithering::None),
+            [0, 255, 0]
+        );
+
+        // Dithering keeps the average of a flat area between two steps
+        let flat = vec![0.25 / 255.0; 4096];
+        assert!(quantize_f32(&flat, 8, Dithering::None)
+            .iter()
+            .all(|&v| v == 0));
+        let dithered = quantize_f32(&flat, 8, Dithering::Noise);
+        let mean = dithered.iter().map(|&v| f32::from(v)).sum::<f32>() / 4096.0;
+        assert!((mean - 0.25).abs() < 0.05, "{mean}");
+        assert_eq!(dithered, quantize_f32(&flat, 8, Dithering::Noise));
+    }
+
+    #[test]
+    #[should_panic = "bit depth"]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_invalid_bit_depth() {
+        _ = normalize_u16(&[0], 17);
+    }
+}
@@ -17,70 +17,81 @@
 
 //! Decoder of JPEG XL format
 
-use std::{mem::MaybeUninit, ptr::null};
+use std::{mem::MaybeUninit, ptr::null, sync::Arc};
 
 #[allow(clippy::wildcard_imports)]
 use jpegxl_sys::{
     codestream_header::{JxlBasicInfo, JxlOrientation},
     decode::*,
-    types::{JxlDataType, JxlPixelFormat},
+    types::{JxlBool, JxlDataType, JxlPixelFormat},
 };
 
 use crate::{
-    common::{Endianness, PixelType},
+    cms::{self, ColorManagement},
+    common::{CancellationToken, PixelType},
     errors::{check_dec_status, DecodeError},
     memory::MemoryManager,
     parallel::JxlParallelRunner,
-    utils::check_valid_signature,
 };
 
+mod frames;
+pub use frames::*;
+
+mod lenient;
+
+mod pool;
+pub use pool::*;
+
+mod reader;
+pub(crate) use reader::Input;
+
 mod result;
+pub use crate::common::PixelFormat;
 pub use result::*;
 
+mod rows;
+pub use rows::*;
+
+mod send;
+pub use send::*;
+
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::DecodeStats;
+#[cfg(feature = "stats")]
+pub(crate) use stats::StatsRecorder;
+
+mod session;
+pub use session::*;
+
+mod srgb;
+pub use srgb::*;
+
+mod surface;
+pub(crate) use surface::*;
+
+mod thumbnail;
+pub use thumbnail::*;
+
+mod typed;
+pub use typed::*;
+
 /// Basic information
 pub type BasicInfo = JxlBasicInfo;
 /// Progressive decoding steps
 pub type ProgressiveDetail = JxlProgressiveDetail;
 /// Orientation
 pub type Orientation = JxlOrientation;
-
-/// Desired Pixel Format
-#[derive(Clone, Copy, Debug)]
-pub struct PixelFormat {
-    /// Amount of channels available in a pixel buffer.
-    ///
-    /// 1. single-channel data, e.g. grayscale or a single extra channel
-    /// 2. single-channel + alpha
-    /// 3. trichromatic, e.g. RGB
-    /// 4. trichromatic + alpha
-    ///
-    /// # Default
-    /// 0, which means determined automatically from color channels and alpha bits
-    pub num_channels: u32,
-    /// Whether multibyte data types are represented in big endian or little
-    /// endian format. This applies to `u16`, `f16`, and `f32`.
-    ///
-    /// # Default
-    /// [`Endianness::Native`]
-    pub endianness: Endianness,
-    /// Align scanlines to a multiple of align bytes.
-    ///
-    /// # Default
-    /// 0, which means requiring no alignment (which has the same effect as value 1)
-    pub align: usize,
-}
-
-impl Default for PixelFormat {
-    fn default() -> Self {
-        Self {
-            num_channels: 0,
-            endianness: Endianness::Native,
-            align: 0,
-        }
-    }
-}
+/// Color profile to query, either the original one of the image or the one of the returned pixels
+pub type ColorProfileTarget = JxlColorProfileTarget;
 
 /// JPEG XL Decoder
+///
+/// A decoder is neither [`Send`] nor [`Sync`], since the parallel runner and memory
+/// manager it refers to might not be. Build a [`SendDecoder`] with
+/// [`JxlDecoderBuilder::build_send`] to move one to another thread, and use [`SyncDecoder`]
+/// to share decoding between threads.
 #[derive(Builder)]
 #[builder(build_fn(skip, error = "None"))]
 #[builder(setter(strip_option))]
@@ -99,7 +110,8 @@ pub struct JxlDecoder<'pr, 'mm> {
     /// `false`, and the returned pixel data is re-oriented
     pub skip_reorientation: Option<bool>,
     /// Enables or disables preserving of associated alpha channels.
-    /// If it is set to `true`, the colors will be unpremultiplied based on the alpha channel
+    /// If it is set to `true`, the colors will be unpremultiplied based on the alpha channel.
+    /// Whether the output is premultiplied is reported in [`Metadata::alpha_premultiplied`].
     ///
     /// # Default
     /// `false`, and return the pixel data "as is".
@@ -143,6 +155,16 @@ pub struct JxlDecoder<'pr, 'mm> {
     /// `false`
     pub icc_profile: bool,
 
+    /// Set which color profile the ICC profile describes.
+    ///
+    /// [`ColorProfileTarget::Original`] is the profile the image was encoded from, while
+    /// [`ColorProfileTarget::Data`] matches the returned pixels, which differ from the
+    /// original when the image is stored in XYB and converted to another color space on decoding
+    ///
+    /// # Default
+    /// [`ColorProfileTarget::Data`]
+    pub color_profile_target: Option<ColorProfileTarget>,
+
     /// Set initial buffer for JPEG reconstruction
     /// Larger buffer could make reconstruction faster by doing fewer reallocation
     ///
@@ -154,6 +176,19 @@ pub struct JxlDecoder<'pr, 'mm> {
 
     /// Set memory manager
     pub memory_manager: Option<&'mm dyn MemoryManager>,
+
+    /// Set a token to cancel decoding with [`DecodeError::Cancelled`]
+    ///
+    /// # Default
+    /// `None`, and decoding always runs to the end
+    pub cancellation: Option<CancellationToken>,
+
+    /// Set the color management system used when the pixels are converted to another color
+    /// profile, as done by [`decode_to_srgb8_with_cms`]
+    ///
+    /// # Default
+    /// `None`, and the color management system of libjxl is used when needed
+    pub cms: Option<Arc<dyn ColorManagement>>,
 }
 
 impl<'pr, 'mm> JxlDecoderBuilder<'pr, 'mm> {
@@ -174,7 +209,12 @@ impl<'pr, 'mm> JxlDecoderBuilder<'pr, 'mm> {
             return Err(DecodeError::CannotCreateDecoder);
         }
 
-        Ok(JxlDecoder {
+        Ok(self.wrap(dec))
+    }
+
+    // Wrap the underlying decoder with the options of the builder
+    fn wrap(&self, dec: *mut jpegxl_sys::decode::JxlDecoder) -> JxlDecoder<'pr, 'mm> {
+        JxlDecoder {
             dec,
             pixel_format: self.pixel_format.flatten(),
             skip_reorientation: self.skip_reorientation.flatten(),
@@ -185,49 +225,86 @@ impl<'pr, 'mm> JxlDecoderBuilder<'pr, 'mm> {
             decompress: self.decompress.flatten(),
             progressive_detail: self.progressive_detail.flatten(),
             icc_profile: self.icc_profile.unwrap_or_default(),
+            color_profile_target: self.color_profile_target.flatten(),
             init_jpeg_buffer: self.init_jpeg_buffer.unwrap_or(512 * 1024),
             parallel_runner: self.parallel_runner.flatten(),
-            memory_manager: mm,
-        })
+            memory_manager: self.memory_manager.flatten(),
+            cancellation: self.cancellation.clone().flatten(),
+            cms: self.cms.clone().flatten(),
+        }
     }
 }
 
-impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
+impl JxlDecoder<'_, '_> {
+    /// Return [`DecodeError::Cancelled`] if the cancellation token is set
+    pub(crate) fn check_cancelled(&self) -> Result<(), DecodeError> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            // Free the memory held for the partial image
+            unsafe { JxlDecoderReset(self.dec) };
+            return Err(DecodeError::Cancelled);
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "trace", tracing::instrument(level = "debug", skip_all))]
     pub(crate) fn decode_internal(
         &self,
-        data: &[u8],
+        mut input: Input<'_>,
         data_type: Option<JxlDataType>,
         with_icc_profile: bool,
         mut reconstruct_jpeg_buffer: Option<&mut Vec<u8>>,
         format: *mut JxlPixelFormat,
-        pixels: &mut Vec<u8>,
+        mut output: ImageOut<'_>,
     ) -> Result<Metadata, DecodeError> {
-        let Some(sig) = check_valid_signature(data) else {
-            return Err(DecodeError::InvalidInput);
+        input.check_signature()?;
+
+        let applied_orientation = if self.skip_reorientation == Some(true) {
+            Orientation::Identity
+        } else {
+            self.coded_orientation(&mut input)?
         };
-        if !sig {
-            return Err(DecodeError::InvalidInput);
-        }
 
         let mut basic_info = MaybeUninit::uninit();
         let mut icc = if with_icc_profile { Some(vec![]) } else { None };
 
-        self.setup_decoder(with_icc_profile, reconstruct_jpeg_buffer.is_some())?;
+        let mut events = Events::new().want_basic_info().want_full_image();
+        if with_icc_profile {
+            events = events.want_color_profile();
+        }
+        if reconstruct_jpeg_buffer.is_some() {
+            events = events.want_jpeg_reconstruction();
+        }
+        // Progressive steps are only counted
+        #[cfg(feature = "stats")]
+        let (events, mut recorder) = (events.want_frame_progression(), StatsRecorder::start());
 
-        let next_in = data.as_ptr();
-        let avail_in = std::mem::size_of_val(data) as _;
+        // A previous decoding may have stopped early on an error
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(events)?;
 
-        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, next_in, avail_in) })?;
-        unsafe { JxlDecoderCloseInput(self.dec) };
+        input.set(self)?;
 
         let mut status;
         loop {
             use JxlDecoderStatus as s;
 
+            self.check_cancelled()?;
             status = unsafe { JxlDecoderProcessInput(self.dec) };
+            #[cfg(feature = "trace")]
+            tracing::trace!(?status);
+            #[cfg(feature = "stats")]
+            recorder.record(status);
+            if output.panicked() {
+                return Err(DecodeError::CallbackPanicked);
+            }
 
             match status {
-                s::NeedMoreInput | s::Error => return Err(DecodeError::GenericError),
+                s::NeedMoreInput => input.feed(self, false)?,
+                s::Error => return Err(DecodeError::GenericError),
 
                 // Get the basic info
                 s::BasicInfo => {
@@ -271,10 +348,16 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
 
                 // Get the output buffer
                 s::NeedImageOutBuffer => {
-                    self.output(unsafe { &*basic_info.as_ptr() }, data_type, format, pixels)?;
+                    let info = unsafe { &*basic_info.as_ptr() };
+                    match &mut output {
+                        ImageOut::Buffer(pixels) => self.output(info, data_type, format, pixels)?,
+                        ImageOut::Surface(surface) => {
+                            self.output_surface(info, data_type, format, surface)?;
+                        }
+                    }
                 }
 
-                s::FullImage => continue,
+                s::FullImage | s::FrameProgression => {}
                 s::Success => {
                     if let Some(buf) = reconstruct_jpeg_buffer.as_mut() {
                         let remaining = unsafe { JxlDecoderReleaseJPEGBuffer(self.dec) };
@@ -286,50 +369,102 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
                     unsafe { JxlDecoderReset(self.dec) };
 
                     let info = unsafe { basic_info.assume_init() };
-                    return Ok(Metadata {
-                        width: info.xsize,
-                        height: info.ysize,
-                        intensity_target: info.intensity_target,
-                        min_nits: info.min_nits,
-                        orientation: info.orientation,
-                        num_color_channels: info.num_color_channels,
-                        has_alpha_channel: info.alpha_bits > 0,
-                        intrinsic_width: info.intrinsic_xsize,
-                        intrinsic_height: info.intrinsic_ysize,
-                        icc_profile: icc,
-                    });
+                    let metadata = self.metadata(&info, applied_orientation, icc);
+                    #[cfg(feature = "stats")]
+                    let metadata = Metadata {
+                        stats: Some(recorder.finish()),
+                        ..metadata
+                    };
+                    return Ok(metadata);
                 }
                 s::NeedPreviewOutBuffer => todo!(),
                 s::BoxNeedMoreOutput => todo!(),
                 s::PreviewImage => todo!(),
                 s::Frame => todo!(),
                 s::Box => todo!(),
-                s::FrameProgression => todo!(),
             }
         }
     }
 
-    fn setup_decoder(&self, icc: bool, reconstruct_jpeg: bool) -> Result<(), DecodeError> {
+    fn metadata(
+        &self,
+        info: &BasicInfo,
+        applied_orientation: Orientation,
+        icc_profile: Option<Vec<u8>>,
+    ) -> Metadata {
+        let (oriented_width, oriented_height) =
+            oriented_size(info.xsize, info.ysize, info.orientation);
+        Metadata {
+            width: info.xsize,
+            height: info.ysize,
+            oriented_width,
+            oriented_height,
+            intensity_target: info.intensity_target,
+            min_nits: info.min_nits,
+            bits_per_sample: info.bits_per_sample,
+            exponent_bits_per_sample: info.exponent_bits_per_sample,
+            orientation: info.orientation,
+            applied_orientation,
+            num_color_channels: info.num_color_channels,
+            has_alpha_channel: info.alpha_bits > 0,
+            alpha_premultiplied: info.alpha_bits > 0
+                && info.alpha_premultiplied == JxlBool::True
+                && self.unpremul_alpha != Some(true),
+            intrinsic_width: info.intrinsic_xsize,
+            intrinsic_height: info.intrinsic_ysize,
+            icc_profile,
+            #[cfg(feature = "stats")]
+            stats: None,
+            animation: (info.have_animation == JxlBool::True).then_some(Animation {
+                tps_numerator: info.animation.tps_numerator,
+                tps_denominator: info.animation.tps_denominator,
+                num_loops: info.animation.num_loops,
+                have_timecodes: info.animation.have_timecodes == JxlBool::True,
+            }),
+        }
+    }
+
+    /// Read the orientation from the codestream header, which the basic info
+    /// reports as identity once the decoder is set to apply it
+    fn coded_orientation(&self, input: &mut Input<'_>) -> Result<Orientation, DecodeError> {
+        unsafe { JxlDecoderReset(self.dec) };
+        check_dec_status(unsafe { JxlDecoderSetKeepOrientation(self.dec, JxlBool::True) })?;
+        check_dec_status(unsafe {
+            JxlDecoderSubscribeEvents(self.dec, Events::new().want_basic_info().bits())
+        })?;
+        input.set(self)?;
+
+        let mut info = MaybeUninit::uninit();
+        let orientation = loop {
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                // Keep the input read so far, it is decoded again from the start
+                JxlDecoderStatus::NeedMoreInput => input.feed(self, true)?,
+                JxlDecoderStatus::BasicInfo => {
+                    break check_dec_status(unsafe {
+                        JxlDecoderGetBasicInfo(self.dec, info.as_mut_ptr())
+                    })
+                    .map(|()| unsafe { info.assume_init() }.orientation);
+                }
+                _ => break Err(DecodeError::GenericError),
+            }
+        };
+
+        unsafe { JxlDecoderReset(self.dec) };
+        orientation
+    }
+
+    fn setup_decoder(&self, events: Events) -> Result<(), DecodeError> {
         if let Some(runner) = self.parallel_runner {
             check_dec_status(unsafe {
                 JxlDecoderSetParallelRunner(self.dec, runner.runner(), runner.as_opaque_ptr())
             })?;
         }
 
-        let events = {
-            use JxlDecoderStatus::{BasicInfo, ColorEncoding, FullImage, JpegReconstruction};
-
-            let mut events = BasicInfo as i32 | FullImage as i32;
-            if icc {
-                events |= ColorEncoding as i32;
-            }
-            if reconstruct_jpeg {
-                events |= JpegReconstruction as i32;
-            }
+        check_dec_status(unsafe { JxlDecoderSubscribeEvents(self.dec, events.bits()) })?;
 
-            events
-        };
-        check_dec_status(unsafe { JxlDecoderSubscribeEvents(self.dec, events) })?;
+        if let Some(cms) = &self.cms {
+            check_dec_status(unsafe { JxlDecoderSetCms(self.dec, cms::interface(cms)) })?;
+        }
 
         if let Some(val) = self.skip_reorientation {
             check_dec_status(unsafe { JxlDecoderSetKeepOrientation(self.dec, val.into()) })?;
@@ -346,37 +481,44 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
         if let Some(val) = self.desired_intensity_target {
             check_dec_status(unsafe { JxlDecoderSetDesiredIntensityTarget(self.dec, val) })?;
         }
+        if let Some(val) = self.decompress {
+            check_dec_status(unsafe { JxlDecoderSetDecompressBoxes(self.dec, val.into()) })?;
+        }
+        if events.contains(JxlDecoderStatus::FrameProgression) {
+            check_dec_status(unsafe {
+                JxlDecoderSetProgressiveDetail(
+                    self.dec,
+                    self.progressive_detail.unwrap_or(JxlProgressiveDetail::DC),
+                )
+            })?;
+        }
 
         Ok(())
     }
 
     fn get_icc_profile(&self, icc_profile: &mut Vec<u8>) -> Result<(), DecodeError> {
+        let target = self
+            .color_profile_target
+            .unwrap_or(JxlColorProfileTarget::Data);
+
         let mut icc_size = 0;
-        check_dec_status(unsafe {
-            JxlDecoderGetICCProfileSize(self.dec, JxlColorProfileTarget::Data, &mut icc_size)
-        })?;
+        check_dec_status(unsafe { JxlDecoderGetICCProfileSize(self.dec, target, &mut icc_size) })?;
         icc_profile.resize(icc_size, 0);
 
         check_dec_status(unsafe {
-            JxlDecoderGetColorAsICCProfile(
-                self.dec,
-                JxlColorProfileTarget::Data,
-                icc_profile.as_mut_ptr(),
-                icc_size,
-            )
+            JxlDecoderGetColorAsICCProfile(self.dec, target, icc_profile.as_mut_ptr(), icc_size)
         })?;
 
         Ok(())
     }
 
-    fn output(
+    pub(crate) fn resolve_pixel_format(
         &self,
         info: &BasicInfo,
         data_type: Option<JxlDataType>,
-        format: *mut JxlPixelFormat,
-        pixels: &mut Vec<u8>,
-    ) -> Result<(), DecodeError> {
-        let data_type = match data_type {
+    ) -> Result<JxlPixelFormat, DecodeError> {
+        let f = self.pixel_format.unwrap_or_default();
+        let data_type = match data_type.or(f.data_type) {
             Some(v) => v,
             None => match (info.bits_per_sample, info.exponent_bits_per_sample) {
                 (x, 0) if x <= 8 => JxlDataType::Uint8,
@@ -387,17 +529,20 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
             },
         };
 
-        let f = self.pixel_format.unwrap_or_default();
-        let pixel_format = JxlPixelFormat {
-            num_channels: if f.num_channels == 0 {
-                info.num_color_channels + u32::from(info.alpha_bits > 0)
-            } else {
-                f.num_channels
-            },
+        Ok(f.to_jxl(
+            info.num_color_channels + u32::from(info.alpha_bits > 0),
             data_type,
-            endianness: f.endianness,
-            align: f.align,
-        };
+        ))
+    }
+
+    fn output(
+        &self,
+        info: &BasicInfo,
+        data_type: Option<JxlDataType>,
+        format: *mut JxlPixelFormat,
+        pixels: &mut Vec<u8>,
+    ) -> Result<(), DecodeError> {
+        let pixel_format = self.resolve_pixel_format(info, data_type)?;
 
         let mut size = 0;
         check_dec_status(unsafe {
@@ -413,20 +558,70 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
         Ok(())
     }
 
+    fn output_surface(
+        &self,
+        info: &BasicInfo,
+        data_type: Option<JxlDataType>,
+        format: *mut JxlPixelFormat,
+        surface: &mut Surface<'_>,
+    ) -> Result<(), DecodeError> {
+        let pixel_format = self.resolve_pixel_format(info, data_type)?;
+
+        let sample_size = match pixel_format.data_type {
+            JxlDataType::Uint8 => 1,
+            JxlDataType::Uint16 | JxlDataType::Float16 => 2,
+            JxlDataType::Float => 4,
+        };
+        surface.pixel_size = pixel_format.num_channels as usize * sample_size;
+
+        // The last row only takes `row_size` bytes, and sizes too large to compute cannot fit
+        let row_size = (info.xsize as usize).checked_mul(surface.pixel_size);
+        let height = info.ysize as usize;
+        if height > 0 {
+            let needed = row_size
+                .filter(|&row_size| surface.stride >= row_size)
+                .and_then(|row_size| {
+                    surface
+                        .stride
+                        .checked_mul(height - 1)?
+                        .checked_add(row_size)
+                });
+            if !needed.is_some_and(|needed| surface.len >= needed) {
+                return Err(DecodeError::SurfaceTooSmall);
+            }
+        }
+
+        check_dec_status(unsafe {
+            JxlDecoderSetImageOutCallback(
+                self.dec,
+                &pixel_format,
+                Surface::write,
+                std::ptr::addr_of_mut!(*surface).cast(),
+            )
+        })?;
+
+        unsafe { *format = pixel_format };
+        Ok(())
+    }
+
     /// Decode a JPEG XL image
     ///
     /// # Errors
     /// Return a [`DecodeError`] when internal decoder fails
     pub fn decode(&self, data: &[u8]) -> Result<(Metadata, Pixels), DecodeError> {
+        self.decode_input(Input::Slice(data))
+    }
+
+    fn decode_input(&self, input: Input<'_>) -> Result<(Metadata, Pixels), DecodeError> {
         let mut buffer = vec![];
         let mut pixel_format = MaybeUninit::uninit();
         let metadata = self.decode_internal(
-            data,
+            input,
             None,
             self.icc_profile,
             None,
             pixel_format.as_mut_ptr(),
-            &mut buffer,
+            ImageOut::Buffer(&mut buffer),
         )?;
         Ok((
             metadata,
@@ -441,16 +636,23 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
     pub fn decode_with<T: PixelType>(
         &self,
         data: &[u8],
+    ) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decode_input_with(Input::Slice(data))
+    }
+
+    fn decode_input_with<T: PixelType>(
+        &self,
+        input: Input<'_>,
     ) -> Result<(Metadata, Vec<T>), DecodeError> {
         let mut buffer = vec![];
         let mut pixel_format = MaybeUninit::uninit();
         let metadata = self.decode_internal(
-            data,
+            input,
             Some(T::pixel_type()),
             self.icc_profile,
             None,
             pixel_format.as_mut_ptr(),
-            &mut buffer,
+            ImageOut::Buffer(&mut buffer),
         )?;
 
         // Safety: type `T` is set by user and provide to the decoder to determine output data type
@@ -463,6 +665,169 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
         Ok((metadata, buf))
     }
 
+    /// Decode a JPEG XL image to the raw bytes of a specific pixel type
+    ///
+    /// Multibyte samples are laid out in the `endianness` of the decoder's `pixel_format`,
+    /// so setting [`Endianness::Big`](crate::Endianness::Big) gives the network byte order
+    /// expected by PNG writers without swapping the bytes again. Scanline alignment is kept.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode_bytes_with<T: PixelType>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Vec<u8>), DecodeError> {
+        let mut buffer = vec![];
+        let mut pixel_format = MaybeUninit::uninit();
+        let metadata = self.decode_internal(
+            Input::Slice(data),
+            Some(T::pixel_type()),
+            self.icc_profile,
+            None,
+            pixel_format.as_mut_ptr(),
+            ImageOut::Buffer(&mut buffer),
+        )?;
+        Ok((metadata, buffer))
+    }
+
+    /// Decode an integer JPEG XL image of up to 24 bits per sample to `u32` samples
+    ///
+    /// Samples range from 0 to `2^bits_per_sample - 1`, as given in [`Metadata::bits_per_sample`].
+    /// libjxl has no integer output wider than 16 bits, so the samples are decoded to `f32`
+    /// and scaled back. libjxl turns an integer sample `v` into `v * (1 / max)`, rounded to
+    /// `f32`, and multiplying back by `max` rounds to `v` for every sample of every bit depth
+    /// up to 24, so lossless images come out unchanged. Lossy images get the nearest integer
+    /// of their decoded value. An alpha channel is scaled to the bit depth of the color
+    /// channels.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::UnsupportedBitWidth`] for floating point images or images with
+    /// more than 24 bits per sample, or a [`DecodeError`] when internal decoder fails
+    pub fn decode_u32(&self, data: &[u8]) -> Result<(Metadata, Vec<u32>), DecodeError> {
+        let (metadata, _, pixels) = self.decode_unpadded::<f32>(data)?;
+        let bits = metadata.bits_per_sample;
+        if metadata.exponent_bits_per_sample > 0 || bits > 24 {
+            return Err(DecodeError::UnsupportedBitWidth(bits));
+        }
+
+        let max = (1u32 << bits) - 1;
+        let pixels = pixels.into_iter().map(|v| sample_to_u32(v, max)).collect();
+        Ok((metadata, pixels))
+    }
+
+    /// Decode a JPEG XL image to a specific pixel type, with each channel in its own plane
+    ///
+    /// Planes follow the channel order of the interleaved output, e.g. R, G, B, A,
+    /// and each one is `width * height` samples without any scanline alignment.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode_planar_with<T: PixelType + Copy>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Vec<Vec<T>>), DecodeError> {
+        let (metadata, channels, pixels) = self.decode_unpadded::<T>(data)?;
+
+        let mut planes = vec![Vec::with_capacity(pixels.len() / channels); channels];
+        for pixel in pixels.chunks_exact(channels) {
+            for (plane, &sample) in planes.iter_mut().zip(pixel) {
+                plane.push(sample);
+            }
+        }
+
+        Ok((metadata, planes))
+    }
+
+    /// Decode to interleaved samples with the scanline alignment removed,
+    /// also returning the number of channels per pixel
+    pub(crate) fn decode_unpadded<T: PixelType>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, usize, Vec<T>), DecodeError> {
+        let mut buffer = vec![];
+        let mut pixel_format = MaybeUninit::uninit();
+        let metadata = self.decode_internal(
+            Input::Slice(data),
+            Some(T::pixel_type()),
+            self.icc_profile,
+            None,
+            pixel_format.as_mut_ptr(),
+            ImageOut::Buffer(&mut buffer),
+        )?;
+
+        let pixel_format = unsafe { pixel_format.assume_init() };
+        let channels = pixel_format.num_channels as usize;
+
+        let row_size = metadata.width as usize * channels * std::mem::size_of::<T>();
+        let stride = if pixel_format.align > 1 {
+            row_size.div_ceil(pixel_format.align) * pixel_format.align
+        } else {
+            row_size
+        };
+
+        let pixels = if stride == row_size {
+            T::convert(&buffer, &pixel_format)
+        } else {
+            buffer
+                .chunks(stride)
+                .flat_map(|row| T::convert(&row[..row_size], &pixel_format))
+                .collect()
+        };
+
+        Ok((metadata, channels, pixels))
+    }
+
+    /// Decode a JPEG XL image to a specific pixel type, directly into an existing surface
+    ///
+    /// Row `y` of the image is written to `surface[y * stride..]` as raw samples in the
+    /// decoder's `pixel_format` endianness; bytes past the end of each row are left untouched.
+    /// The `align` of the pixel format is ignored in favor of `stride`.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails, or
+    /// [`DecodeError::SurfaceTooSmall`] when `stride` or `surface` cannot hold the image
+    pub fn decode_into_surface<T: PixelType>(
+        &self,
+        data: &[u8],
+        surface: &mut [u8],
+        stride: usize,
+    ) -> Result<Metadata, DecodeError> {
+        let mut pixel_format = MaybeUninit::uninit();
+        self.decode_internal(
+            Input::Slice(data),
+            Some(T::pixel_type()),
+            self.icc_profile,
+            None,
+            pixel_format.as_mut_ptr(),
+            ImageOut::Surface(Surface::new(surface, stride)),
+        )
+    }
+
+    /// Count the frames in the image without decoding any pixels
+    ///
+    /// With [`JxlDecoder::coalescing`] disabled, every layer is counted as its own frame.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn count_frames(&self, data: &[u8]) -> Result<usize, DecodeError> {
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(Events::new().want_frame())?;
+        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, data.as_ptr(), data.len()) })?;
+        unsafe { JxlDecoderCloseInput(self.dec) };
+
+        let mut count = 0;
+        let result = loop {
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                JxlDecoderStatus::Frame => count += 1,
+                JxlDecoderStatus::Success => break Ok(count),
+                _ => break Err(DecodeError::GenericError),
+            }
+        };
+
+        unsafe { JxlDecoderReset(self.dec) };
+        result
+    }
+
     /// Reconstruct JPEG data. Fallback to pixels if JPEG reconstruction fails
     ///
     /// # Note
@@ -475,12 +840,12 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
         let mut pixel_format = MaybeUninit::uninit();
         let mut jpeg_buf = vec![];
         let metadata = self.decode_internal(
-            data,
+            Input::Slice(data),
             None,
             self.icc_profile,
             Some(&mut jpeg_buf),
             pixel_format.as_mut_ptr(),
-            &mut buffer,
+            ImageOut::Buffer(&mut buffer),
         )?;
 
         Ok((
@@ -494,7 +859,7 @@ impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
     }
 }
 
-impl<'prl, 'mm> Drop for JxlDecoder<'prl, 'mm> {
+impl Drop for JxlDecoder<'_, '_> {
     fn drop(&mut self) {
         unsafe { JxlDecoderDestroy(self.dec) };
     }
@@ -506,6 +871,14 @@ pub fn decoder_builder<'prl, 'mm>() -> JxlDecoderBuilder<'prl, 'mm> {
     JxlDecoderBuilder::default()
 }
 
+/// Scale a sample decoded to `f32` back to an integer from 0 to `max`
+///
+/// The product is taken in `f64`, which holds it exactly for `max` of up to 24 bits.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn sample_to_u32(sample: f32, max: u32) -> u32 {
+    (f64::from(sample.clamp(0.0, 1.0)) * f64::from(max)).round() as u32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -518,4 +891,26 @@ mod tests {
 
         _ = decoder_builder().clone();
     }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn test_sample_to_u32() {
+        // Every sample of every depth, converted to `f32` the way libjxl does for lossless
+        // images: with a single precision factor below 23 bits, a double precision one above
+        for bits in 1..=24 {
+            let max = (1u32 << bits) - 1;
+            let factor = 1.0 / f64::from(max);
+            for v in 0..=max {
+                let sample = if bits < 23 {
+                    f64::from(v) as f32 * factor as f32
+                } else {
+                    (f64::from(v) * factor) as f32
+                };
+                assert_eq!(sample_to_u32(sample, max), v, "{v} at {bits} bits");
+            }
+        }
+
+        assert_eq!(sample_to_u32(-0.5, 255), 0);
+        assert_eq!(sample_to_u32(1.5, 255), 255);
+    }
 }
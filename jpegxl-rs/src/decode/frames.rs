@@ -0,0 +1,96 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::marker::PhantomData;
+
+use super::{Events, FrameHeader, JxlDecoder, Session, Status};
+use crate::{common::PixelType, errors::DecodeError};
+
+/// Frame returned by [`Frames`]
+#[derive(Debug)]
+pub struct Frame<T> {
+    /// Header of the frame, with its duration and name length
+    pub header: FrameHeader,
+    /// Pixels of the frame, in the decoder's `pixel_format`
+    pub pixels: Vec<T>,
+}
+
+/// Iterator over the frames of an image, created by [`JxlDecoder::frames`]
+///
+/// Zero-duration frames are merged into the next displayed one unless
+/// [`JxlDecoder::coalescing`] is disabled.
+pub struct Frames<'a, 'pr, 'mm, T: PixelType> {
+    session: Session<'a, 'pr, 'mm>,
+    header: Option<FrameHeader>,
+    done: bool,
+    _pixel_type: PhantomData<T>,
+}
+
+impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
+    /// Decode the frames one at a time, in pixels of type `T`
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the decoder fails to set up
+    pub fn frames<'a, T: PixelType>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Frames<'a, 'pr, 'mm, T>, DecodeError> {
+        Ok(Frames {
+            session: self.session(data, Events::new().want_frame().want_full_image())?,
+            header: None,
+            done: false,
+            _pixel_type: PhantomData,
+        })
+    }
+}
+
+impl<T: PixelType> Frames<'_, '_, '_, T> {
+    fn next_frame(&mut self) -> Result<Option<Frame<T>>, DecodeError> {
+        loop {
+            match self.session.process()? {
+                Status::Frame => self.header = Some(self.session.frame_header()?),
+                Status::NeedImageOutBuffer => self.session.set_image_out_buffer::<T>()?,
+                Status::FullImage => {
+                    let header = self.header.take().ok_or(DecodeError::GenericError)?;
+                    let pixels = self
+                        .session
+                        .take_pixels_as::<T>()
+                        .ok_or(DecodeError::GenericError)?;
+                    return Ok(Some(Frame { header, pixels }));
+                }
+                Status::Success => return Ok(None),
+                Status::NeedMoreInput => return Err(DecodeError::GenericError),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<T: PixelType> Iterator for Frames<'_, '_, '_, T> {
+    type Item = Result<Frame<T>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let frame = self.next_frame().transpose();
+        self.done = !matches!(frame, Some(Ok(_)));
+        frame
+    }
+}
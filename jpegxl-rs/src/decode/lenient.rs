@@ -0,0 +1,187 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::mem::MaybeUninit;
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::{decode::*, types::JxlPixelFormat};
+
+use super::{BasicInfo, Completeness, Events, Input, JxlDecoder, Metadata, Orientation};
+use crate::{
+    common::PixelType,
+    errors::{check_dec_status, DecodeError},
+    utils::{box_header, check_valid_signature, container_boxes},
+};
+
+impl JxlDecoder<'_, '_> {
+    /// Decode a JPEG XL image to a specific pixel type, tolerating truncated input
+    ///
+    /// When the input ends early, the partially decoded image is returned along with
+    /// [`Completeness::Truncated`] instead of an error. Image regions not covered by the
+    /// available input come out blurry or blank.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails, or when the input ends
+    /// before any pixels could be decoded
+    pub fn decode_lenient_with<T: PixelType>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Vec<T>, Completeness), DecodeError> {
+        if check_valid_signature(data) != Some(true) {
+            return Err(DecodeError::InvalidInput);
+        }
+
+        let applied_orientation = if self.skip_reorientation == Some(true) {
+            Orientation::Identity
+        } else {
+            self.coded_orientation(&mut Input::Slice(data))?
+        };
+
+        let mut events = Events::new().want_basic_info().want_full_image();
+        if self.icc_profile {
+            events = events.want_color_profile();
+        }
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(events)?;
+
+        // Input is left open, so running out of it is reported as `NeedMoreInput`
+        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, data.as_ptr(), data.len()) })?;
+
+        let mut basic_info = MaybeUninit::uninit();
+        let mut icc = if self.icc_profile { Some(vec![]) } else { None };
+        let mut buffer = vec![];
+        let result = self.decode_lenient_loop::<T>(data, &mut basic_info, &mut icc, &mut buffer);
+        unsafe { JxlDecoderReset(self.dec) };
+
+        let (pixel_format, completeness) = result?;
+        // Safety: the output buffer is only requested after the basic info
+        let info = unsafe { basic_info.assume_init() };
+        Ok((
+            self.metadata(&info, applied_orientation, icc),
+            T::convert(&buffer, &pixel_format),
+            completeness,
+        ))
+    }
+
+    fn decode_lenient_loop<T: PixelType>(
+        &self,
+        data: &[u8],
+        basic_info: &mut MaybeUninit<BasicInfo>,
+        icc: &mut Option<Vec<u8>>,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(JxlPixelFormat, Completeness), DecodeError> {
+        let mut pixel_format = None;
+
+        let completeness = loop {
+            use JxlDecoderStatus as s;
+
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                s::Error => return Err(DecodeError::GenericError),
+                s::BasicInfo => {
+                    check_dec_status(unsafe {
+                        JxlDecoderGetBasicInfo(self.dec, basic_info.as_mut_ptr())
+                    })?;
+
+                    if let Some(pr) = self.parallel_runner {
+                        pr.callback_basic_info(unsafe { &*basic_info.as_ptr() });
+                    }
+                }
+                s::ColorEncoding => {
+                    self.get_icc_profile(unsafe { icc.as_mut().unwrap_unchecked() })?;
+                }
+                s::NeedImageOutBuffer => {
+                    let mut format = MaybeUninit::uninit();
+                    self.output(
+                        unsafe { &*basic_info.as_ptr() },
+                        Some(T::pixel_type()),
+                        format.as_mut_ptr(),
+                        buffer,
+                    )?;
+                    pixel_format = Some(unsafe { format.assume_init() });
+                }
+                s::FullImage => {}
+                s::Success => break Completeness::Complete,
+                s::NeedMoreInput => {
+                    if pixel_format.is_none() {
+                        return Err(DecodeError::GenericError);
+                    }
+                    // Render what has been decoded so far into the output buffer,
+                    // this fails harmlessly if a full image was already written
+                    unsafe { JxlDecoderFlushImage(self.dec) };
+                    break Completeness::Truncated {
+                        bytes_missing: missing_bytes(data),
+                    };
+                }
+                status => return Err(DecodeError::UnknownStatus(status)),
+            }
+        };
+
+        pixel_format
+            .map(|f| (f, completeness))
+            .ok_or(DecodeError::GenericError)
+    }
+}
+
+/// Bytes missing from a truncated container, according to the size of its last box.
+/// Return `None` for a bare codestream or when the size is unknown
+fn missing_bytes(data: &[u8]) -> Option<u64> {
+    const CONTAINER_SIGNATURE: [u8; 12] =
+        [0, 0, 0, 0xC, b'J', b'X', b'L', b' ', 0xD, 0xA, 0x87, 0xA];
+    if !data.starts_with(&CONTAINER_SIGNATURE) {
+        return None;
+    }
+
+    let mut rest = data;
+    for b in container_boxes(data) {
+        let Ok(b) = b else {
+            // Only the last box is truncated, its header tells how long it should be
+            let (_, _, size) = box_header(rest)?;
+            return size.checked_sub(rest.len() as u64);
+        };
+        // A box extending to the end of the file has no known size
+        if rest.starts_with(&[0; 4]) {
+            return None;
+        }
+        rest = &rest[usize::try_from(b.size).ok()?..];
+    }
+
+    Some(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_missing_bytes() {
+        let signature = [0, 0, 0, 0xC, b'J', b'X', b'L', b' ', 0xD, 0xA, 0x87, 0xA];
+        let with_box = |header: &[u8]| [&signature[..], header].concat();
+
+        assert_eq!(missing_bytes(&signature), Some(0));
+        assert_eq!(missing_bytes(&with_box(b"\0\0\0\x20jxlc\0\0")), Some(22));
+        assert_eq!(missing_bytes(&with_box(b"\0\0\0\0jxlc\0\0")), None);
+        assert_eq!(missing_bytes(&[0xFF, 0x0A, 0]), None);
+
+        // Malformed sizes, smaller than the header, are rejected instead of looping
+        let largesize_zero = with_box(b"\0\0\0\x01jxlc\0\0\0\0\0\0\0\0");
+        assert_eq!(missing_bytes(&largesize_zero), None);
+        assert_eq!(missing_bytes(&with_box(b"\0\0\0\x02jxlc")), None);
+    }
+}
@@ -0,0 +1,180 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::sync::{Mutex, PoisonError};
+
+use super::SendDecoder;
+use crate::errors::DecodeError;
+
+type MakeDecoder = dyn Fn() -> Result<SendDecoder<'static, 'static>, DecodeError> + Send + Sync;
+
+/// Decoder which can be shared between threads
+///
+/// A [`SendDecoder`] can be moved to another thread but not shared, since a libjxl decoder
+/// decodes one image at a time. This wrapper keeps the decoders not in use, hands one out
+/// to each call and takes it back afterwards, building another only when all of them are
+/// in use. It holds as many decoders as calls ever ran at once, whichever threads made them.
+///
+/// The decoders are dropped with the wrapper.
+///
+/// # Example
+/// ```
+/// use jpegxl_rs::{decode::SyncDecoder, decoder_builder};
+///
+/// let decoder = SyncDecoder::new(|| decoder_builder().build_send(None, None));
+/// std::thread::scope(|s| {
+///     s.spawn(|| decoder.with(|d| d.decode_with::<u8>(&[])));
+///     s.spawn(|| decoder.with(|d| d.decode_with::<u8>(&[])));
+/// });
+/// ```
+pub struct SyncDecoder {
+    idle: Mutex<Vec<SendDecoder<'static, 'static>>>,
+    make: Box<MakeDecoder>,
+}
+
+impl SyncDecoder {
+    /// Create with the function building a decoder when none is idle
+    pub fn new<F>(make: F) -> Self
+    where
+        F: Fn() -> Result<SendDecoder<'static, 'static>, DecodeError> + Send + Sync + 'static,
+    {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            make: Box::new(make),
+        }
+    }
+
+    /// Run `f` with an idle decoder
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when building the decoder fails, or the error of `f`
+    pub fn with<T, F>(&self, f: F) -> Result<T, DecodeError>
+    where
+        F: FnOnce(&mut SendDecoder<'static, 'static>) -> Result<T, DecodeError>,
+    {
+        // Take the decoder out while it is used, so nested calls build their own
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let mut decoder = match idle {
+            Some(decoder) => decoder,
+            None => (self.make)()?,
+        };
+
+        let result = f(&mut decoder);
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(decoder);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Barrier,
+        },
+        thread,
+    };
+
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        cms::ColorManagement,
+        decoder_builder,
+        tests::{UnusedCms, SAMPLE_JXL},
+    };
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_sync_decoder() -> TestResult {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&builds);
+        let decoder = SyncDecoder::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+            decoder_builder().build_send(None, None)
+        });
+        assert_send_sync(&decoder);
+
+        // Threads running one after the other share the decoder
+        for _ in 0..2 {
+            thread::scope(|s| s.spawn(|| decoder.with(|d| d.decode(SAMPLE_JXL))).join())
+                .expect("Decoding thread panicked")?;
+        }
+        assert_eq!(builds.load(Ordering::Relaxed), 1);
+
+        // Nested calls get their own decoder
+        decoder.with(|outer| decoder.with(|inner| Ok(outer.dec != inner.dec)))?;
+        assert_eq!(builds.load(Ordering::Relaxed), 2);
+
+        // Calls running at once on other threads reuse both
+        let barrier = Barrier::new(2);
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        decoder.with(|d| {
+                            barrier.wait();
+                            d.decode(SAMPLE_JXL)
+                        })
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .try_for_each(|h| h.join().expect("Decoding thread panicked").map(drop))
+        })?;
+        assert_eq!(builds.load(Ordering::Relaxed), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_sync_decoder_drop() -> TestResult {
+        let cms: Arc<dyn ColorManagement> = Arc::new(UnusedCms);
+        let shared = Arc::clone(&cms);
+        let decoder = Arc::new(SyncDecoder::new(move || {
+            decoder_builder()
+                .cms(Arc::clone(&shared))
+                .build_send(None, None)
+        }));
+
+        // The decoder of an exited thread is kept for the next call
+        let worker = {
+            let decoder = Arc::clone(&decoder);
+            thread::spawn(move || decoder.with(|_| Ok(())))
+        };
+        worker.join().expect("Decoding thread panicked")?;
+        decoder.with(|_| Ok(()))?;
+        // Held by the build function and the only decoder
+        assert_eq!(Arc::strong_count(&cms), 3);
+
+        drop(decoder);
+        assert_eq!(Arc::strong_count(&cms), 1);
+
+        Ok(())
+    }
+}
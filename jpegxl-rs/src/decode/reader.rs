@@ -0,0 +1,146 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Input of the decoder, in memory or read in chunks
+
+use std::io::Read;
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::decode::*;
+
+use super::{rows::INPUT_CHUNK, JxlDecoder, Metadata, Pixels};
+use crate::{
+    common::PixelType,
+    errors::{check_dec_status, DecodeError},
+    utils::check_valid_signature,
+};
+
+/// Where the decoder takes its input from
+pub(crate) enum Input<'d> {
+    /// Whole input in memory
+    Slice(&'d [u8]),
+    /// Input read in chunks, keeping only what the decoder has not consumed yet
+    Reader {
+        reader: &'d mut dyn Read,
+        buffer: Vec<u8>,
+        eof: bool,
+    },
+}
+
+impl<'d> Input<'d> {
+    pub(crate) fn reader(reader: &'d mut dyn Read) -> Self {
+        Self::Reader {
+            reader,
+            buffer: vec![],
+            eof: false,
+        }
+    }
+
+    /// Check the signature at the start of the input, reading the first chunk
+    pub(crate) fn check_signature(&mut self) -> Result<(), DecodeError> {
+        let data = match self {
+            Self::Slice(data) => *data,
+            Self::Reader {
+                reader,
+                buffer,
+                eof,
+            } => {
+                *eof = read_chunk(reader, buffer)? == 0;
+                buffer
+            }
+        };
+        match check_valid_signature(data) {
+            Some(true) => Ok(()),
+            _ => Err(DecodeError::InvalidInput),
+        }
+    }
+
+    /// Hand the input held in memory to a freshly reset decoder
+    pub(crate) fn set(&self, decoder: &JxlDecoder) -> Result<(), DecodeError> {
+        let (data, eof) = match self {
+            Self::Slice(data) => (*data, true),
+            Self::Reader { buffer, eof, .. } => (buffer.as_slice(), *eof),
+        };
+        check_dec_status(unsafe { JxlDecoderSetInput(decoder.dec, data.as_ptr(), data.len()) })?;
+        if eof {
+            unsafe { JxlDecoderCloseInput(decoder.dec) };
+        }
+        Ok(())
+    }
+
+    /// Hand the unconsumed input plus the next chunk to the decoder, in response to
+    /// `NeedMoreInput`. With `keep`, the consumed input stays in memory to be [`set`](Self::set)
+    /// again after a reset
+    pub(crate) fn feed(&mut self, decoder: &JxlDecoder, keep: bool) -> Result<(), DecodeError> {
+        let Self::Reader {
+            reader,
+            buffer,
+            eof: eof @ false,
+        } = self
+        else {
+            return Err(DecodeError::GenericError);
+        };
+
+        let remaining = unsafe { JxlDecoderReleaseInput(decoder.dec) };
+        let mut start = buffer.len() - remaining;
+        if !keep {
+            buffer.drain(..start);
+            start = 0;
+        }
+        *eof = read_chunk(reader, buffer)? == 0;
+
+        check_dec_status(unsafe {
+            JxlDecoderSetInput(decoder.dec, buffer[start..].as_ptr(), buffer.len() - start)
+        })?;
+        if *eof {
+            unsafe { JxlDecoderCloseInput(decoder.dec) };
+        }
+        Ok(())
+    }
+}
+
+/// Append up to a chunk of input to `buffer`, returning the number of bytes read
+fn read_chunk(reader: &mut dyn Read, buffer: &mut Vec<u8>) -> Result<usize, DecodeError> {
+    Ok(reader.take(INPUT_CHUNK as u64).read_to_end(buffer)?)
+}
+
+impl JxlDecoder<'_, '_> {
+    /// Decode a JPEG XL image read from `reader`
+    ///
+    /// The input is handed to the decoder in chunks, so only the part not consumed yet is
+    /// held in memory.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when reading fails, or another [`DecodeError`] when the
+    /// internal decoder fails
+    pub fn decode_reader(&self, mut reader: impl Read) -> Result<(Metadata, Pixels), DecodeError> {
+        self.decode_input(Input::reader(&mut reader))
+    }
+
+    /// Decode a JPEG XL image read from `reader` to a specific pixel type, see
+    /// [`decode_reader`](Self::decode_reader)
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when reading fails, or another [`DecodeError`] when the
+    /// internal decoder fails
+    pub fn decode_reader_with<T: PixelType>(
+        &self,
+        mut reader: impl Read,
+    ) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decode_input_with(Input::reader(&mut reader))
+    }
+}
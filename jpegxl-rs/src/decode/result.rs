@@ -15,6 +15,8 @@ You should have received a copy of the GNU General Public License
 along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 */
 
+use std::time::Duration;
+
 use half::f16;
 use jpegxl_sys::types::{JxlDataType, JxlPixelFormat};
 
@@ -23,21 +25,41 @@ use crate::common::PixelType;
 
 /// Result of decoding
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Metadata {
-    /// Width of the image
+    /// Width of the returned pixels
     pub width: u32,
-    /// Height of the image
+    /// Height of the returned pixels
     pub height: u32,
+    /// Width of the image once displayed in its intended orientation
+    pub oriented_width: u32,
+    /// Height of the image once displayed in its intended orientation
+    pub oriented_height: u32,
     /// Upper bound on the intensity level present in the image in nits
     pub intensity_target: f32,
     /// Lower bound on the intensity level present in the image
     pub min_nits: f32,
-    /// Orientation
+    /// Bits per sample of the color channels, from metadata
+    pub bits_per_sample: u32,
+    /// Exponent bits per sample of the color channels, from metadata.
+    /// Non-zero for floating point images
+    pub exponent_bits_per_sample: u32,
+    /// Orientation still to be applied to the returned pixels.
+    ///
+    /// [`Orientation::Identity`] unless
+    /// [`JxlDecoder::skip_reorientation`](super::JxlDecoder::skip_reorientation) is set
     pub orientation: Orientation,
+    /// Orientation the decoder already applied to the returned pixels
+    pub applied_orientation: Orientation,
     /// Number of color channels per pixel _without_ alpha channel, from metadata
     pub num_color_channels: u32,
     /// Whether the image has an alpha channel, from metadata
     pub has_alpha_channel: bool,
+    /// Whether the returned color channels are premultiplied by alpha.
+    ///
+    /// This is the associated alpha flag from metadata, unless it was undone by
+    /// [`JxlDecoder::unpremul_alpha`](super::JxlDecoder::unpremul_alpha)
+    pub alpha_premultiplied: bool,
     /// Intrinsic width of the image.
     /// Applications are advised to resample the decoded image to the intrinsic dimensions
     pub intrinsic_width: u32,
@@ -46,6 +68,64 @@ pub struct Metadata {
     pub intrinsic_height: u32,
     /// ICC profile
     pub icc_profile: Option<Vec<u8>>,
+    /// Animation timing, `None` for still images
+    pub animation: Option<Animation>,
+    /// Decoding statistics, only recorded by [`JxlDecoder::decode`](super::JxlDecoder::decode)
+    /// and the other methods decoding the whole input in one go
+    #[cfg(feature = "stats")]
+    pub stats: Option<super::DecodeStats>,
+}
+
+/// Size of a `width` x `height` image after applying `orientation`
+pub(crate) fn oriented_size(width: u32, height: u32, orientation: Orientation) -> (u32, u32) {
+    match orientation {
+        Orientation::Transpose
+        | Orientation::Rotate90Cw
+        | Orientation::AntiTranspose
+        | Orientation::Rotate90Ccw => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// Animation header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Animation {
+    /// Numerator of ticks per second of a single animation frame time unit
+    pub tps_numerator: u32,
+    /// Denominator of ticks per second of a single animation frame time unit
+    pub tps_denominator: u32,
+    /// Amount of animation loops, or 0 to repeat infinitely
+    pub num_loops: u32,
+    /// Whether animation time codes are present at animation frames in the codestream
+    pub have_timecodes: bool,
+}
+
+impl Animation {
+    /// Convert a frame duration in ticks, as found in the frame header, to wall time
+    #[must_use]
+    pub fn ticks_to_duration(&self, ticks: u32) -> Duration {
+        if self.tps_numerator == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(
+            f64::from(ticks) * f64::from(self.tps_denominator) / f64::from(self.tps_numerator),
+        )
+    }
+}
+
+/// Whether all of the input was decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Completeness {
+    /// The image was fully decoded
+    Complete,
+    /// The input ended early, and the image is only partially decoded
+    Truncated {
+        /// Amount of bytes missing from the end of the input, if it can be told from
+        /// the container
+        bytes_missing: Option<u64>,
+    },
 }
 
 /// Pixels returned from the decoder
@@ -92,17 +172,63 @@ mod tests {
             Metadata {
                 width: 0,
                 height: 0,
+                oriented_width: 0,
+                oriented_height: 0,
                 intensity_target: 0.0,
                 min_nits: 0.0,
+                bits_per_sample: 0,
+                exponent_bits_per_sample: 0,
                 orientation: Orientation::Identity,
+                applied_orientation: Orientation::Identity,
                 num_color_channels: 0,
                 has_alpha_channel: false,
+                alpha_premultiplied: false,
                 intrinsic_width: 0,
                 intrinsic_height: 0,
                 icc_profile: None,
+                animation: None,
+                #[cfg(feature = "stats")]
+                stats: None,
             }
         );
 
         println!("{:?}", Pixels::Float(vec![]));
     }
+
+    #[test]
+    fn test_oriented_size() {
+        assert_eq!(oriented_size(3, 2, Orientation::Identity), (3, 2));
+        assert_eq!(oriented_size(3, 2, Orientation::Rotate180), (3, 2));
+        assert_eq!(oriented_size(3, 2, Orientation::FlipVertical), (3, 2));
+        assert_eq!(oriented_size(3, 2, Orientation::Transpose), (2, 3));
+        assert_eq!(oriented_size(3, 2, Orientation::Rotate90Cw), (2, 3));
+        assert_eq!(oriented_size(3, 2, Orientation::Rotate90Ccw), (2, 3));
+    }
+
+    #[test]
+    fn test_ticks() {
+        let animation = Animation {
+            tps_numerator: 100,
+            tps_denominator: 1,
+            num_loops: 0,
+            have_timecodes: false,
+        };
+        assert_eq!(animation.ticks_to_duration(5), Duration::from_millis(50));
+
+        let animation = Animation {
+            tps_numerator: 30000,
+            tps_denominator: 1001,
+            ..animation
+        };
+        assert_eq!(
+            animation.ticks_to_duration(30),
+            Duration::from_secs_f64(1.001)
+        );
+
+        let animation = Animation {
+            tps_numerator: 0,
+            ..animation
+        };
+        assert_eq!(animation.ticks_to_duration(1), Duration::ZERO);
+    }
 }
@@ -0,0 +1,304 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::{decode::*, types::JxlPixelFormat};
+
+use super::{BasicInfo, Events, JxlDecoder};
+use crate::{
+    common::PixelType,
+    errors::{check_dec_status, DecodeError},
+    utils::check_valid_signature,
+};
+
+/// Amount of input handed to the decoder at a time
+pub(crate) const INPUT_CHUNK: usize = 64 * 1024;
+
+/// Horizontal run of decoded pixels, yielded by [`Rows`]
+#[derive(Debug)]
+pub struct RowChunk<T> {
+    /// Column of the first pixel
+    pub x: usize,
+    /// Row of the pixels
+    pub y: usize,
+    /// Interleaved samples of the pixels
+    pub data: Vec<T>,
+}
+
+/// Iterator over decoded pixel runs, created by [`JxlDecoder::decode_rows`]
+///
+/// Only the first frame is decoded. Runs are yielded as soon as the decoder renders them,
+/// which is not necessarily in top to bottom order, and may cover only part of a row.
+/// The decoder is reset when the iterator is dropped.
+pub struct Rows<'a, 'pr, 'mm, T: PixelType> {
+    decoder: &'a mut JxlDecoder<'pr, 'mm>,
+    data: &'a [u8],
+    fed: usize,
+    info: BasicInfo,
+    pixel_format: Option<JxlPixelFormat>,
+    // Boxed to keep its address stable for the image out callback
+    queue: Box<RowQueue>,
+    done: bool,
+    _pixel_type: PhantomData<T>,
+}
+
+#[derive(Default)]
+struct RowQueue {
+    pixel_size: usize,
+    // The callback can be called from multiple threads of the parallel runner
+    runs: Mutex<VecDeque<(usize, usize, Vec<u8>)>>,
+    // Set when the callback panicked, since it cannot unwind into libjxl
+    panicked: AtomicBool,
+}
+
+impl RowQueue {
+    extern "C" fn push(
+        opaque: *mut c_void,
+        x: usize,
+        y: usize,
+        num_pixels: usize,
+        pixels: *const c_void,
+    ) {
+        // Safety: `opaque` is the queue passed to `JxlDecoderSetImageOutCallback`,
+        // which lives as long as the decoder is not reset
+        let queue = unsafe { &*opaque.cast::<Self>() };
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(pixels.cast::<u8>(), num_pixels * queue.pixel_size)
+            };
+            if let Ok(mut runs) = queue.runs.lock() {
+                runs.push_back((x, y, bytes.to_vec()));
+            }
+        }));
+        if result.is_err() {
+            queue.panicked.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
+    /// Decode a JPEG XL image to pixels of type `T` a few rows at a time
+    ///
+    /// The input is handed to the decoder in chunks and no output buffer is allocated,
+    /// so only the rows rendered from the latest chunk are held at once.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the basic information cannot be decoded
+    pub fn decode_rows<'a, T: PixelType>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Rows<'a, 'pr, 'mm, T>, DecodeError> {
+        if check_valid_signature(data) != Some(true) {
+            return Err(DecodeError::InvalidInput);
+        }
+
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(Events::new().want_basic_info().want_full_image())?;
+
+        let mut fed = 0;
+        let result = self.decode_rows_info(data, &mut fed);
+        let info = match result {
+            Ok(info) => info,
+            Err(e) => {
+                unsafe { JxlDecoderReset(self.dec) };
+                return Err(e);
+            }
+        };
+
+        Ok(Rows {
+            decoder: self,
+            data,
+            fed,
+            info,
+            pixel_format: None,
+            queue: Box::default(),
+            done: false,
+            _pixel_type: PhantomData,
+        })
+    }
+
+    fn decode_rows_info(&self, data: &[u8], fed: &mut usize) -> Result<BasicInfo, DecodeError> {
+        loop {
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                JxlDecoderStatus::NeedMoreInput => self.feed_chunk(data, fed)?,
+                JxlDecoderStatus::BasicInfo => {
+                    let mut info = MaybeUninit::uninit();
+                    check_dec_status(unsafe {
+                        JxlDecoderGetBasicInfo(self.dec, info.as_mut_ptr())
+                    })?;
+                    let info = unsafe { info.assume_init() };
+
+                    if let Some(pr) = self.parallel_runner {
+                        pr.callback_basic_info(&info);
+                    }
+                    return Ok(info);
+                }
+                _ => return Err(DecodeError::GenericError),
+            }
+        }
+    }
+
+    /// Hand the unconsumed input plus the next chunk to the decoder
+    fn feed_chunk(&self, data: &[u8], fed: &mut usize) -> Result<(), DecodeError> {
+        if *fed == data.len() {
+            return Err(DecodeError::GenericError);
+        }
+
+        let remaining = unsafe { JxlDecoderReleaseInput(self.dec) };
+        let start = *fed - remaining;
+        let end = (*fed + INPUT_CHUNK).min(data.len());
+        check_dec_status(unsafe {
+            JxlDecoderSetInput(self.dec, data[start..].as_ptr(), end - start)
+        })?;
+        if end == data.len() {
+            unsafe { JxlDecoderCloseInput(self.dec) };
+        }
+
+        *fed = end;
+        Ok(())
+    }
+}
+
+impl<T: PixelType> Rows<'_, '_, '_, T> {
+    /// Get the basic information of the image
+    #[must_use]
+    pub fn basic_info(&self) -> &BasicInfo {
+        &self.info
+    }
+
+    fn step(&mut self) -> Result<(), DecodeError> {
+        use JxlDecoderStatus as s;
+
+        let status = unsafe { JxlDecoderProcessInput(self.decoder.dec) };
+        if self.queue.panicked.load(Ordering::Relaxed) {
+            return Err(DecodeError::CallbackPanicked);
+        }
+
+        match status {
+            s::NeedMoreInput => self.decoder.feed_chunk(self.data, &mut self.fed),
+            s::NeedImageOutBuffer => {
+                let format = self
+                    .decoder
+                    .resolve_pixel_format(&self.info, Some(T::pixel_type()))?;
+                self.queue.pixel_size = format.num_channels as usize * std::mem::size_of::<T>();
+
+                check_dec_status(unsafe {
+                    JxlDecoderSetImageOutCallback(
+                        self.decoder.dec,
+                        &format,
+                        RowQueue::push,
+                        std::ptr::addr_of!(*self.queue).cast_mut().cast(),
+                    )
+                })?;
+                self.pixel_format = Some(format);
+                Ok(())
+            }
+            s::FullImage | s::Success => {
+                self.done = true;
+                Ok(())
+            }
+            s::Error => Err(DecodeError::GenericError),
+            status => Err(DecodeError::UnknownStatus(status)),
+        }
+    }
+}
+
+impl<T: PixelType> Iterator for Rows<'_, '_, '_, T> {
+    type Item = Result<RowChunk<T>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let run = self.queue.runs.lock().ok()?.pop_front();
+            if let (Some((x, y, bytes)), Some(format)) = (run, &self.pixel_format) {
+                return Some(Ok(RowChunk {
+                    x,
+                    y,
+                    data: T::convert(&bytes, format),
+                }));
+            }
+
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.step() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<T: PixelType> Drop for Rows<'_, '_, '_, T> {
+    fn drop(&mut self) {
+        unsafe { JxlDecoderReset(self.decoder.dec) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{decoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_push_panic() {
+        let queue = RowQueue {
+            pixel_size: usize::MAX,
+            ..RowQueue::default()
+        };
+        // The size overflows, which panics with debug assertions
+        RowQueue::push(
+            std::ptr::addr_of!(queue).cast_mut().cast(),
+            0,
+            0,
+            2,
+            std::ptr::NonNull::<u8>::dangling().as_ptr().cast(),
+        );
+        assert!(queue.panicked.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_callback_panicked() -> TestResult {
+        let mut decoder = decoder_builder().build()?;
+        let mut rows = decoder.decode_rows::<u8>(SAMPLE_JXL)?;
+        rows.queue.panicked.store(true, Ordering::Relaxed);
+
+        assert!(matches!(
+            rows.next(),
+            Some(Err(DecodeError::CallbackPanicked))
+        ));
+        assert!(rows.next().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Decoder which can be moved to another thread
+
+use std::ops::Deref;
+
+use super::{Frames, JxlDecoder, JxlDecoderBuilder, Rows, Session};
+use crate::{
+    common::PixelType, decode::Events, errors::DecodeError, memory::MemoryManager,
+    parallel::JxlParallelRunner,
+};
+
+impl<'pr, 'mm> JxlDecoderBuilder<'pr, 'mm> {
+    /// Build a [`SendDecoder`], which can be moved to another thread
+    ///
+    /// The parallel runner and memory manager are given here instead, replacing any set on
+    /// the builder, as the decoder uses them from whichever thread it is moved to.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::CannotCreateDecoder`] if it fails to create the decoder.
+    pub fn build_send(
+        &self,
+        parallel_runner: Option<&'pr (dyn JxlParallelRunner + Sync)>,
+        memory_manager: Option<&'mm (dyn MemoryManager + Sync)>,
+    ) -> Result<SendDecoder<'pr, 'mm>, DecodeError> {
+        let mut builder = self.clone();
+        builder.parallel_runner = Some(parallel_runner.map(|pr| pr as &dyn JxlParallelRunner));
+        builder.memory_manager = Some(memory_manager.map(|mm| mm as &dyn MemoryManager));
+        builder.build().map(SendDecoder)
+    }
+}
+
+/// [`JxlDecoder`] which is [`Send`], built with [`JxlDecoderBuilder::build_send`]
+///
+/// Its parallel runner and memory manager are [`Sync`], and the decoder is never lent out
+/// mutably so they cannot be replaced. Decoding methods borrowing the decoder immutably
+/// are available through [`Deref`], the others are forwarded. Options are the ones given
+/// to the builder.
+pub struct SendDecoder<'pr, 'mm>(pub(crate) JxlDecoder<'pr, 'mm>);
+
+// SAFETY: a libjxl decoder may be used from any thread, one at a time, which `&mut self`
+// and the lack of `Sync` ensure. The parallel runner and memory manager it refers to are
+// `Sync`, and everything else it holds is `Send`.
+unsafe impl Send for SendDecoder<'_, '_> {}
+
+impl<'pr, 'mm> Deref for SendDecoder<'pr, 'mm> {
+    type Target = JxlDecoder<'pr, 'mm>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'pr, 'mm> SendDecoder<'pr, 'mm> {
+    /// Start a low-level decoding session, see [`JxlDecoder::session`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the decoder fails to set up
+    pub fn session<'a>(
+        &'a mut self,
+        data: &'a [u8],
+        events: Events,
+    ) -> Result<Session<'a, 'pr, 'mm>, DecodeError> {
+        self.0.session(data, events)
+    }
+
+    /// Decode the frames one at a time, see [`JxlDecoder::frames`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the decoder fails to set up
+    pub fn frames<'a, T: PixelType>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Frames<'a, 'pr, 'mm, T>, DecodeError> {
+        self.0.frames(data)
+    }
+
+    /// Decode a few rows at a time, see [`JxlDecoder::decode_rows`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the basic information cannot be decoded
+    pub fn decode_rows<'a, T: PixelType>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Rows<'a, 'pr, 'mm, T>, DecodeError> {
+        self.0.decode_rows(data)
+    }
+
+    /// Decode a JPEG XL image in a new file at `path`, see [`JxlDecoder::decode_to_file`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be created or mapped, or a
+    /// [`DecodeError`] when internal decoder fails
+    #[cfg(feature = "memmap2")]
+    pub fn decode_to_file<T: PixelType>(
+        &mut self,
+        data: &[u8],
+        path: impl AsRef<std::path::Path>,
+        format: crate::FileFormat,
+    ) -> Result<super::Metadata, DecodeError> {
+        self.0.decode_to_file::<T>(data, path, format)
+    }
+
+    /// Unwrap the inner [`JxlDecoder`], which is not [`Send`]
+    #[must_use]
+    pub fn into_inner(self) -> JxlDecoder<'pr, 'mm> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use testresult::TestResult;
+
+    use crate::{
+        decoder_builder, memory::RustAllocator, parallel::shared_runner::SharedRunner,
+        tests::SAMPLE_JXL,
+    };
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_send_decoder() -> TestResult {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let runner = SharedRunner::default();
+        let mut decoder = decoder_builder()
+            .skip_reorientation(true)
+            .build_send(Some(&runner), Some(&RustAllocator))?;
+        assert_send(&decoder);
+        assert!(decoder.parallel_runner.is_some() && decoder.memory_manager.is_some());
+
+        let expected = decoder.decode_with::<u8>(SAMPLE_JXL)?.1;
+        decoder = thread::scope(|s| {
+            s.spawn(move || {
+                assert_eq!(decoder.skip_reorientation, Some(true));
+                assert_eq!(decoder.decode_with::<u8>(SAMPLE_JXL)?.1, expected);
+                assert!(decoder.frames::<u8>(SAMPLE_JXL)?.next().is_some());
+                Ok::<_, crate::DecodeError>(decoder)
+            })
+            .join()
+            .expect("Decoding thread panicked")
+        })?;
+        assert!(decoder.into_inner().memory_manager.is_some());
+
+        Ok(())
+    }
+}
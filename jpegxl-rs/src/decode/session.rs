@@ -0,0 +1,487 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{marker::PhantomData, mem::MaybeUninit};
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::{
+    codestream_header::JxlFrameHeader,
+    decode::*,
+    types::{JxlBoxType, JxlPixelFormat},
+};
+
+use super::{BasicInfo, JxlDecoder, Pixels};
+use crate::{
+    common::PixelType,
+    errors::{check_dec_status, DecodeError},
+    utils::check_valid_signature,
+};
+
+/// Status returned by [`Session::process`]
+pub type Status = JxlDecoderStatus;
+/// Frame header
+pub type FrameHeader = JxlFrameHeader;
+
+/// Largest box buffer allocated up front, as the box size comes from the input
+const BOX_CHUNK: usize = 4096;
+
+/// Events to subscribe to when driving the decoder with a [`Session`]
+///
+/// # Example
+/// ```
+/// use jpegxl_rs::decode::Events;
+///
+/// let events = Events::new()
+///     .want_basic_info()
+///     .want_color_profile()
+///     .want_boxes()
+///     .want_preview();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Events(i32);
+
+impl Events {
+    /// Subscribe to no events
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with(self, status: JxlDecoderStatus) -> Self {
+        Self(self.0 | status as i32)
+    }
+
+    /// Emit [`Status::BasicInfo`] once the basic image information is available
+    #[must_use]
+    pub fn want_basic_info(self) -> Self {
+        self.with(JxlDecoderStatus::BasicInfo)
+    }
+
+    /// Emit [`Status::ColorEncoding`] once the color profile is available
+    #[must_use]
+    pub fn want_color_profile(self) -> Self {
+        self.with(JxlDecoderStatus::ColorEncoding)
+    }
+
+    /// Emit [`Status::PreviewImage`] once the preview image is decoded
+    #[must_use]
+    pub fn want_preview(self) -> Self {
+        self.with(JxlDecoderStatus::PreviewImage)
+    }
+
+    /// Emit [`Status::Frame`] at the beginning of each frame
+    #[must_use]
+    pub fn want_frame(self) -> Self {
+        self.with(JxlDecoderStatus::Frame)
+    }
+
+    /// Emit [`Status::FullImage`] once each frame is fully decoded
+    #[must_use]
+    pub fn want_full_image(self) -> Self {
+        self.with(JxlDecoderStatus::FullImage)
+    }
+
+    /// Emit [`Status::JpegReconstruction`] when JPEG reconstruction data is available
+    #[must_use]
+    pub fn want_jpeg_reconstruction(self) -> Self {
+        self.with(JxlDecoderStatus::JpegReconstruction)
+    }
+
+    /// Emit [`Status::Box`] at the beginning of each container box.
+    /// Its contents are read with [`Session::set_box_buffer`]
+    #[must_use]
+    pub fn want_boxes(self) -> Self {
+        self.with(JxlDecoderStatus::Box)
+    }
+
+    /// Emit [`Status::FrameProgression`] at the progressive steps configured by
+    /// [`JxlDecoder::progressive_detail`]
+    #[must_use]
+    pub fn want_frame_progression(self) -> Self {
+        self.with(JxlDecoderStatus::FrameProgression)
+    }
+
+    /// Check if the event is subscribed
+    #[must_use]
+    pub fn contains(self, status: JxlDecoderStatus) -> bool {
+        self.0 & status as i32 != 0
+    }
+
+    pub(crate) fn bits(self) -> i32 {
+        self.0
+    }
+}
+
+/// Low-level decoding session, created by [`JxlDecoder::session`]
+///
+/// Call [`Session::process`] repeatedly and react to the returned [`Status`] until it
+/// returns [`Status::Success`]. The decoder is reset when the session is dropped.
+///
+/// # Example
+/// ```
+/// # || -> Result<(), Box<dyn std::error::Error>> {
+/// use jpegxl_rs::{decode::{Events, Status}, decoder_builder};
+///
+/// let sample = include_bytes!("../../../samples/sample.jxl");
+/// let mut decoder = decoder_builder().build()?;
+/// let mut session = decoder.session(sample, Events::new().want_basic_info().want_full_image())?;
+/// loop {
+///     match session.process()? {
+///         Status::BasicInfo => println!("{}", session.basic_info()?.xsize),
+///         Status::NeedImageOutBuffer => session.set_image_out_buffer::<u8>()?,
+///         Status::FullImage => { let _pixels = session.take_pixels(); }
+///         Status::Success => break,
+///         _ => {}
+///     }
+/// }
+/// # Ok(())
+/// # };
+/// ```
+pub struct Session<'a, 'pr, 'mm> {
+    decoder: &'a mut JxlDecoder<'pr, 'mm>,
+    pixel_format: Option<JxlPixelFormat>,
+    pixels: Vec<u8>,
+    preview_format: Option<JxlPixelFormat>,
+    preview: Vec<u8>,
+    box_contents: Vec<u8>,
+    box_buffer_set: bool,
+    _data: PhantomData<&'a [u8]>,
+}
+
+impl<'pr, 'mm> JxlDecoder<'pr, 'mm> {
+    /// Start a low-level decoding session over `data`, emitting the subscribed `events`
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the decoder fails to set up
+    pub fn session<'a>(
+        &'a mut self,
+        data: &'a [u8],
+        events: Events,
+    ) -> Result<Session<'a, 'pr, 'mm>, DecodeError> {
+        if check_valid_signature(data) != Some(true) {
+            return Err(DecodeError::InvalidInput);
+        }
+
+        // Reset first, so a failed setup does not leave options from this session behind
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(events)?;
+        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, data.as_ptr(), data.len()) })?;
+        unsafe { JxlDecoderCloseInput(self.dec) };
+
+        Ok(Session {
+            decoder: self,
+            pixel_format: None,
+            pixels: vec![],
+            preview_format: None,
+            preview: vec![],
+            box_contents: vec![],
+            box_buffer_set: false,
+            _data: PhantomData,
+        })
+    }
+
+    /// Read the basic information of `data` without decoding any pixels
+    pub(crate) fn read_basic_info(&mut self, data: &[u8]) -> Result<BasicInfo, DecodeError> {
+        let mut session = self.session(data, Events::new().want_basic_info())?;
+        loop {
+            match session.process()? {
+                Status::BasicInfo => return session.basic_info(),
+                Status::NeedMoreInput | Status::Success => return Err(DecodeError::InvalidInput),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Session<'_, '_, '_> {
+    /// Process the input until the next subscribed event or request is reached
+    ///
+    /// # Errors
+    /// Return [`DecodeError::GenericError`] when the decoder fails, or
+    /// [`DecodeError::Cancelled`] when its cancellation token is set
+    pub fn process(&mut self) -> Result<Status, DecodeError> {
+        self.decoder.check_cancelled()?;
+        let status = unsafe { JxlDecoderProcessInput(self.decoder.dec) };
+        #[cfg(feature = "trace")]
+        tracing::trace!(?status, "session");
+        match status {
+            JxlDecoderStatus::Error => Err(DecodeError::GenericError),
+            status => Ok(status),
+        }
+    }
+
+    /// Get the basic information, available after [`Status::BasicInfo`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when the information is not available yet
+    pub fn basic_info(&self) -> Result<BasicInfo, DecodeError> {
+        let mut info = MaybeUninit::uninit();
+        check_dec_status(unsafe { JxlDecoderGetBasicInfo(self.decoder.dec, info.as_mut_ptr()) })?;
+        Ok(unsafe { info.assume_init() })
+    }
+
+    /// Get the ICC profile, available after [`Status::ColorEncoding`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when the profile is not available yet
+    pub fn icc_profile(&self) -> Result<Vec<u8>, DecodeError> {
+        let mut icc_profile = vec![];
+        self.decoder.get_icc_profile(&mut icc_profile)?;
+        Ok(icc_profile)
+    }
+
+    /// Get the header of the current frame, available after [`Status::Frame`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when the header is not available yet
+    pub fn frame_header(&self) -> Result<FrameHeader, DecodeError> {
+        let mut header = MaybeUninit::uninit();
+        check_dec_status(unsafe {
+            JxlDecoderGetFrameHeader(self.decoder.dec, header.as_mut_ptr())
+        })?;
+        Ok(unsafe { header.assume_init() })
+    }
+
+    /// Skip decoding the current frame, valid after [`Status::Frame`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when there is no frame to skip
+    pub fn skip_current_frame(&mut self) -> Result<(), DecodeError> {
+        check_dec_status(unsafe { JxlDecoderSkipCurrentFrame(self.decoder.dec) })
+    }
+
+    /// Get the type of the current box, available after [`Status::Box`].
+    /// The type of compressed `brob` boxes is decompressed if [`JxlDecoder::decompress`] is set
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when there is no current box
+    pub fn box_type(&self) -> Result<[u8; 4], DecodeError> {
+        let mut box_type = JxlBoxType([0; 4]);
+        check_dec_status(unsafe {
+            JxlDecoderGetBoxType(
+                self.decoder.dec,
+                &mut box_type,
+                self.decoder.decompress.unwrap_or_default().into(),
+            )
+        })?;
+        Ok(box_type.0.map(|c| c.to_ne_bytes()[0]))
+    }
+
+    /// Get the raw size of the current box including its header, available after
+    /// [`Status::Box`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when there is no current box
+    pub fn box_size(&self) -> Result<u64, DecodeError> {
+        let mut size = 0;
+        check_dec_status(unsafe { JxlDecoderGetBoxSizeRaw(self.decoder.dec, &mut size) })?;
+        Ok(size)
+    }
+
+    /// Allocate the buffer for the contents of the current box, in response to
+    /// [`Status::Box`]
+    ///
+    /// The contents of a previous box not taken with [`Session::take_box_contents`] are
+    /// discarded. Compressed `brob` boxes are decompressed if [`JxlDecoder::decompress`] is set.
+    /// The buffer starts small and is enlarged with [`Session::grow_box_buffer`] as the box
+    /// is read, so a bogus box size cannot allocate ahead of the data actually read.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when there is no current box or the decoder rejects the buffer
+    pub fn set_box_buffer(&mut self) -> Result<(), DecodeError> {
+        self.take_box_contents();
+
+        let mut size = 0;
+        check_dec_status(unsafe { JxlDecoderGetBoxSizeContents(self.decoder.dec, &mut size) })?;
+        let size = usize::try_from(size).map_or(BOX_CHUNK, |size| size.min(BOX_CHUNK));
+        self.box_contents.resize(size, 0);
+        check_dec_status(unsafe {
+            JxlDecoderSetBoxBuffer(
+                self.decoder.dec,
+                self.box_contents.as_mut_ptr(),
+                self.box_contents.len(),
+            )
+        })?;
+
+        self.box_buffer_set = true;
+        Ok(())
+    }
+
+    /// Enlarge the box buffer, in response to [`Status::BoxNeedMoreOutput`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when no box buffer was set or the decoder rejects the buffer
+    pub fn grow_box_buffer(&mut self) -> Result<(), DecodeError> {
+        if !self.box_buffer_set {
+            return Err(DecodeError::GenericError);
+        }
+
+        let remaining = unsafe { JxlDecoderReleaseBoxBuffer(self.decoder.dec) };
+        let written = self.box_contents.len() - remaining;
+        self.box_contents
+            .resize((self.box_contents.len() * 2).max(BOX_CHUNK), 0);
+        check_dec_status(unsafe {
+            JxlDecoderSetBoxBuffer(
+                self.decoder.dec,
+                self.box_contents[written..].as_mut_ptr(),
+                self.box_contents.len() - written,
+            )
+        })
+    }
+
+    /// Take the contents of the box out of the box buffer, once the next [`Status::Box`]
+    /// or [`Status::Success`] is reached
+    ///
+    /// Return `None` if no box buffer was set
+    pub fn take_box_contents(&mut self) -> Option<Vec<u8>> {
+        if !std::mem::take(&mut self.box_buffer_set) {
+            return None;
+        }
+
+        let remaining = unsafe { JxlDecoderReleaseBoxBuffer(self.decoder.dec) };
+        let mut contents = std::mem::take(&mut self.box_contents);
+        contents.truncate(contents.len() - remaining);
+        Some(contents)
+    }
+
+    /// Allocate the output buffer for pixels of type `T`, in response to
+    /// [`Status::NeedImageOutBuffer`]. The decoder's `pixel_format` is respected
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when the decoder rejects the buffer
+    pub fn set_image_out_buffer<T: PixelType>(&mut self) -> Result<(), DecodeError> {
+        let info = self.basic_info()?;
+        let mut format = MaybeUninit::uninit();
+        self.decoder.output(
+            &info,
+            Some(T::pixel_type()),
+            format.as_mut_ptr(),
+            &mut self.pixels,
+        )?;
+        self.pixel_format = Some(unsafe { format.assume_init() });
+        Ok(())
+    }
+
+    /// Take the decoded pixels out of the output buffer, after [`Status::FullImage`]
+    ///
+    /// Return `None` if no output buffer was set
+    pub fn take_pixels(&mut self) -> Option<Pixels> {
+        self.pixel_format
+            .take()
+            .map(|f| Pixels::new(std::mem::take(&mut self.pixels), &f))
+    }
+
+    /// Take the decoded pixels as `T`, which must be the type the buffer was set for
+    pub(crate) fn take_pixels_as<T: PixelType>(&mut self) -> Option<Vec<T>> {
+        self.pixel_format
+            .take()
+            .map(|f| T::convert(&std::mem::take(&mut self.pixels), &f))
+    }
+
+    /// Allocate the output buffer for the preview in pixels of type `T`, in response to
+    /// [`Status::NeedPreviewOutBuffer`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when the decoder rejects the buffer
+    pub fn set_preview_out_buffer<T: PixelType>(&mut self) -> Result<(), DecodeError> {
+        let info = self.basic_info()?;
+        let format = self
+            .decoder
+            .resolve_pixel_format(&info, Some(T::pixel_type()))?;
+
+        let mut size = 0;
+        check_dec_status(unsafe {
+            JxlDecoderPreviewOutBufferSize(self.decoder.dec, &format, &mut size)
+        })?;
+        self.preview.resize(size, 0);
+        check_dec_status(unsafe {
+            JxlDecoderSetPreviewOutBuffer(
+                self.decoder.dec,
+                &format,
+                self.preview.as_mut_ptr().cast(),
+                size,
+            )
+        })?;
+
+        self.preview_format = Some(format);
+        Ok(())
+    }
+
+    /// Take the decoded preview out of the output buffer, after [`Status::PreviewImage`]
+    ///
+    /// Return `None` if no preview buffer was set
+    pub fn take_preview(&mut self) -> Option<Pixels> {
+        self.preview_format
+            .take()
+            .map(|f| Pixels::new(std::mem::take(&mut self.preview), &f))
+    }
+}
+
+impl Drop for Session<'_, '_, '_> {
+    fn drop(&mut self) {
+        unsafe { JxlDecoderReset(self.decoder.dec) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use testresult::TestResult;
+
+    #[test]
+    fn test_events() {
+        let events = Events::new().want_color_profile().want_boxes();
+
+        assert!(events.contains(JxlDecoderStatus::ColorEncoding));
+        assert!(events.contains(JxlDecoderStatus::Box));
+        assert!(!events.contains(JxlDecoderStatus::PreviewImage));
+        assert_eq!(
+            events.want_preview().bits(),
+            JxlDecoderStatus::ColorEncoding as i32
+                | JxlDecoderStatus::Box as i32
+                | JxlDecoderStatus::PreviewImage as i32
+        );
+    }
+
+    #[test]
+    fn test_box_buffer_bounded() -> TestResult {
+        // Signature and `ftyp` boxes, then an `Exif` box claiming 1 TiB of contents
+        let mut data = b"\0\0\0\x0cJXL \r\n\x87\n\0\0\0\x14ftypjxl \0\0\0\0jxl ".to_vec();
+        data.extend(b"\0\0\0\x01Exif");
+        data.extend((1u64 << 40).to_be_bytes());
+        data.extend([0; 16]);
+
+        let mut decoder = crate::decoder_builder().build()?;
+        let mut session = decoder.session(&data, Events::new().want_boxes())?;
+        loop {
+            match session.process()? {
+                Status::Box if session.box_type()? == *b"Exif" => break,
+                Status::Box => {}
+                status => panic!("unexpected status {status:?}"),
+            }
+        }
+
+        session.set_box_buffer()?;
+        assert_eq!(session.box_contents.len(), BOX_CHUNK);
+        session.grow_box_buffer()?;
+        assert_eq!(session.box_contents.len(), 2 * BOX_CHUNK);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::{mem::MaybeUninit, ptr::null, sync::Arc};
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::{
+    cms::JxlGetDefaultCms, decode::*, encode::JxlColorEncodingSetToSRGB, types::JxlDataType,
+};
+
+use super::{decoder_builder, BasicInfo, Events, JxlDecoder, PixelFormat};
+use crate::{
+    cms::ColorManagement,
+    errors::{check_dec_status, DecodeError},
+    utils::check_valid_signature,
+};
+
+/// Peak luminance of a standard dynamic range display, in nits
+const SDR_INTENSITY_TARGET: f32 = 255.0;
+
+/// Decode a JPEG XL image to 8-bit sRGB pixels ready for display,
+/// compositing transparent images onto white. Return the RGB samples, width and height
+///
+/// See [`decode_to_srgb8_on`] for details.
+///
+/// # Errors
+/// Return a [`DecodeError`] when internal decoder fails
+pub fn decode_to_srgb8(data: &[u8]) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    decode_to_srgb8_on(data, [255; 3])
+}
+
+/// Decode a JPEG XL image to 8-bit sRGB pixels ready for display,
+/// compositing transparent images onto `background`. Return the RGB samples, width and height
+///
+/// Colors are converted to sRGB with the color management system of libjxl, HDR images
+/// are tone mapped to SDR luminance and the image is re-oriented.
+///
+/// # Errors
+/// Return a [`DecodeError`] when internal decoder fails
+pub fn decode_to_srgb8_on(
+    data: &[u8],
+    background: [u8; 3],
+) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    decode_to_srgb8_with_cms(data, background, None)
+}
+
+/// Decode a JPEG XL image to 8-bit sRGB pixels like [`decode_to_srgb8_on`], converting
+/// the colors with `cms` instead of the color management system of libjxl if given
+///
+/// # Errors
+/// Return a [`DecodeError`] when internal decoder fails
+pub fn decode_to_srgb8_with_cms(
+    data: &[u8],
+    background: [u8; 3],
+    cms: Option<Arc<dyn ColorManagement>>,
+) -> Result<(Vec<u8>, u32, u32), DecodeError> {
+    #[cfg(feature = "threads")]
+    let runner = crate::ThreadsRunner::default();
+
+    let mut builder = decoder_builder();
+    builder
+        .unpremul_alpha(true)
+        .desired_intensity_target(SDR_INTENSITY_TARGET)
+        .pixel_format(PixelFormat {
+            num_channels: 4,
+            ..PixelFormat::default()
+        });
+    #[cfg(feature = "threads")]
+    builder.parallel_runner(&runner);
+    if let Some(cms) = cms {
+        builder.cms(cms);
+    }
+    let decoder = builder.build()?;
+
+    let mut rgba = vec![];
+    let (width, height) = decoder.decode_srgb8(data, &mut rgba)?;
+
+    let rgb = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let alpha = u16::from(pixel[3]);
+            std::array::from_fn::<u8, 3, _>(|c| {
+                let blended =
+                    u16::from(pixel[c]) * alpha + u16::from(background[c]) * (255 - alpha);
+                // At most 255 after dividing
+                #[allow(clippy::cast_possible_truncation)]
+                let blended = ((blended + 127) / 255) as u8;
+                blended
+            })
+        })
+        .collect();
+    Ok((rgb, width, height))
+}
+
+impl JxlDecoder<'_, '_> {
+    fn decode_srgb8(&self, data: &[u8], pixels: &mut Vec<u8>) -> Result<(u32, u32), DecodeError> {
+        if check_valid_signature(data) != Some(true) {
+            return Err(DecodeError::InvalidInput);
+        }
+
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(
+            Events::new()
+                .want_basic_info()
+                .want_color_profile()
+                .want_full_image(),
+        )?;
+        // Needed to convert images not stored in XYB
+        if self.cms.is_none() {
+            check_dec_status(unsafe { JxlDecoderSetCms(self.dec, (*JxlGetDefaultCms()).clone()) })?;
+        }
+        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, data.as_ptr(), data.len()) })?;
+        unsafe { JxlDecoderCloseInput(self.dec) };
+
+        let result = self.decode_srgb8_loop(pixels);
+        unsafe { JxlDecoderReset(self.dec) };
+        result
+    }
+
+    fn decode_srgb8_loop(&self, pixels: &mut Vec<u8>) -> Result<(u32, u32), DecodeError> {
+        use JxlDecoderStatus as s;
+
+        let mut basic_info = MaybeUninit::<BasicInfo>::uninit();
+        loop {
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                s::BasicInfo => {
+                    check_dec_status(unsafe {
+                        JxlDecoderGetBasicInfo(self.dec, basic_info.as_mut_ptr())
+                    })?;
+
+                    if let Some(pr) = self.parallel_runner {
+                        pr.callback_basic_info(unsafe { &*basic_info.as_ptr() });
+                    }
+                }
+                s::ColorEncoding => {
+                    let mut srgb = MaybeUninit::uninit();
+                    unsafe { JxlColorEncodingSetToSRGB(srgb.as_mut_ptr(), false) };
+                    check_dec_status(unsafe {
+                        JxlDecoderSetOutputColorProfile(self.dec, srgb.as_ptr(), null(), 0)
+                    })?;
+                }
+                s::NeedImageOutBuffer => {
+                    let mut format = MaybeUninit::uninit();
+                    self.output(
+                        unsafe { &*basic_info.as_ptr() },
+                        Some(JxlDataType::Uint8),
+                        format.as_mut_ptr(),
+                        pixels,
+                    )?;
+                }
+                s::FullImage => {}
+                s::Success => {
+                    let info = unsafe { basic_info.assume_init_ref() };
+                    return Ok((info.xsize, info.ysize));
+                }
+                s::NeedMoreInput | s::Error => return Err(DecodeError::GenericError),
+                status => return Err(DecodeError::UnknownStatus(status)),
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Decoding statistics, enabled by the `stats` feature
+
+use std::time::{Duration, Instant};
+
+use jpegxl_sys::decode::JxlDecoderStatus;
+
+/// Timing and progress of a decoding
+///
+/// Times are measured from the start of the decoding. libjxl does not report how work
+/// is split into groups, so only the events it emits are accounted for.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecodeStats {
+    /// Time until the basic information was decoded
+    pub basic_info: Duration,
+    /// Time until all headers were decoded and pixels were first requested
+    pub headers: Duration,
+    /// Time spent decoding the pixels of all frames
+    pub frames: Duration,
+    /// Total time of the decoding
+    pub total: Duration,
+    /// Number of frames fully decoded
+    pub frame_count: u32,
+    /// Number of progressive steps passed, as configured by
+    /// [`JxlDecoder::progressive_detail`](super::JxlDecoder::progressive_detail)
+    pub progression_steps: u32,
+}
+
+pub(crate) struct StatsRecorder {
+    start: Instant,
+    frame_start: Option<Instant>,
+    stats: DecodeStats,
+}
+
+impl StatsRecorder {
+    pub(crate) fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            frame_start: None,
+            stats: DecodeStats::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, status: JxlDecoderStatus) {
+        use JxlDecoderStatus as s;
+
+        match status {
+            s::BasicInfo => self.stats.basic_info = self.start.elapsed(),
+            s::NeedImageOutBuffer => {
+                if self.stats.headers.is_zero() {
+                    self.stats.headers = self.start.elapsed();
+                }
+                self.frame_start = Some(Instant::now());
+            }
+            s::FrameProgression => self.stats.progression_steps += 1,
+            s::FullImage => {
+                self.stats.frame_count += 1;
+                if let Some(start) = self.frame_start.take() {
+                    self.stats.frames += start.elapsed();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> DecodeStats {
+        self.stats.total = self.start.elapsed();
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testresult::TestResult;
+
+    use crate::{decoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_stats() -> TestResult {
+        let decoder = decoder_builder().build()?;
+        let (metadata, _) = decoder.decode(SAMPLE_JXL)?;
+
+        let stats = metadata.stats.expect("statistics not recorded");
+        println!("{stats:?}");
+        assert_eq!(stats.frame_count, 1);
+        assert!(stats.basic_info <= stats.headers);
+        assert!(stats.headers + stats.frames <= stats.total);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Pixel destinations of the decoder
+
+use std::{
+    ffi::c_void,
+    marker::PhantomData,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Where the decoded pixels are written
+pub(crate) enum ImageOut<'b> {
+    /// Buffer resized to fit the image
+    Buffer(&'b mut Vec<u8>),
+    /// Borrowed surface with a row stride
+    Surface(Surface<'b>),
+}
+
+impl ImageOut<'_> {
+    /// Whether the image out callback panicked
+    pub(crate) fn panicked(&self) -> bool {
+        match self {
+            Self::Buffer(_) => false,
+            Self::Surface(surface) => surface.panicked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Borrowed surface written through the image out callback
+///
+/// The callback may be called from multiple threads at once on disjoint pixels,
+/// so the surface is kept as a raw pointer instead of a mutable slice.
+pub(crate) struct Surface<'b> {
+    ptr: *mut u8,
+    pub(crate) len: usize,
+    pub(crate) stride: usize,
+    pub(crate) pixel_size: usize,
+    // Set when the callback panicked, since it cannot unwind into libjxl
+    panicked: AtomicBool,
+    _buffer: PhantomData<&'b mut [u8]>,
+}
+
+impl<'b> Surface<'b> {
+    pub(crate) fn new(buffer: &'b mut [u8], stride: usize) -> Self {
+        Self {
+            ptr: buffer.as_mut_ptr(),
+            len: buffer.len(),
+            stride,
+            pixel_size: 0,
+            panicked: AtomicBool::new(false),
+            _buffer: PhantomData,
+        }
+    }
+
+    pub(crate) extern "C" fn write(
+        opaque: *mut c_void,
+        x: usize,
+        y: usize,
+        num_pixels: usize,
+        pixels: *const c_void,
+    ) {
+        // Safety: `opaque` is the surface passed to `JxlDecoderSetImageOutCallback`,
+        // which outlives the decoding
+        let surface = unsafe { &*opaque.cast::<Self>() };
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            // Checked, so a position out of the surface is skipped
+            let start = y
+                .checked_mul(surface.stride)
+                .zip(x.checked_mul(surface.pixel_size))
+                .and_then(|(row, col)| row.checked_add(col));
+            let count = num_pixels.checked_mul(surface.pixel_size);
+            let (Some(start), Some(count)) = (start, count) else {
+                return;
+            };
+            if start
+                .checked_add(count)
+                .map_or(true, |end| end > surface.len)
+            {
+                return;
+            }
+
+            // Safety: bounds are checked above, and the decoder never writes the same pixel twice
+            unsafe {
+                std::ptr::copy_nonoverlapping(pixels.cast::<u8>(), surface.ptr.add(start), count);
+            }
+        }));
+        if result.is_err() {
+            surface.panicked.store(true, Ordering::Relaxed);
+        }
+    }
+}
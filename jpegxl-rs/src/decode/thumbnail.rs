@@ -0,0 +1,234 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use std::mem::MaybeUninit;
+
+#[allow(clippy::wildcard_imports)]
+use jpegxl_sys::{
+    decode::*,
+    types::{JxlDataType, JxlEndianness, JxlPixelFormat},
+};
+
+use super::{oriented_size, BasicInfo, Events, Input, JxlDecoder, Orientation};
+use crate::{
+    errors::{check_dec_status, DecodeError},
+    utils::check_valid_signature,
+};
+
+/// Small RGB8 version of an image, created by [`JxlDecoder::decode_thumbnail`]
+#[derive(Debug)]
+pub struct Thumbnail {
+    /// Width of the thumbnail
+    pub width: u32,
+    /// Height of the thumbnail
+    pub height: u32,
+    /// Interleaved RGB samples
+    pub data: Vec<u8>,
+}
+
+impl JxlDecoder<'_, '_> {
+    /// Decode an RGB8 thumbnail whose longest edge is at most `max_edge` pixels
+    ///
+    /// The embedded preview is used when the image has one. Otherwise decoding stops at the
+    /// first progressive step, as configured by [`JxlDecoder::progressive_detail`], and the
+    /// result is downscaled. Both are re-oriented and any alpha channel is dropped.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when internal decoder fails
+    pub fn decode_thumbnail(&self, data: &[u8], max_edge: u32) -> Result<Thumbnail, DecodeError> {
+        if check_valid_signature(data) != Some(true) {
+            return Err(DecodeError::InvalidInput);
+        }
+
+        // The basic info has the main image size oriented, but not the preview size
+        let orientation = if self.skip_reorientation == Some(true) {
+            Orientation::Identity
+        } else {
+            self.coded_orientation(&mut Input::Slice(data))?
+        };
+
+        unsafe { JxlDecoderReset(self.dec) };
+        self.setup_decoder(
+            Events::new()
+                .want_basic_info()
+                .want_preview()
+                .want_frame_progression()
+                .want_full_image(),
+        )?;
+        check_dec_status(unsafe { JxlDecoderSetInput(self.dec, data.as_ptr(), data.len()) })?;
+        unsafe { JxlDecoderCloseInput(self.dec) };
+
+        let mut buffer = vec![];
+        let result = self.decode_thumbnail_loop(&mut buffer, orientation);
+        unsafe { JxlDecoderReset(self.dec) };
+
+        let (width, height) = result?;
+        Ok(downscale(&buffer, width, height, max_edge.max(1)))
+    }
+
+    /// Decode the preview or the first progressive step, returning its dimensions
+    fn decode_thumbnail_loop(
+        &self,
+        buffer: &mut Vec<u8>,
+        orientation: Orientation,
+    ) -> Result<(u32, u32), DecodeError> {
+        use JxlDecoderStatus as s;
+
+        let format = JxlPixelFormat {
+            num_channels: 3,
+            data_type: JxlDataType::Uint8,
+            endianness: JxlEndianness::Native,
+            align: 0,
+        };
+        let mut basic_info = MaybeUninit::<BasicInfo>::uninit();
+
+        loop {
+            match unsafe { JxlDecoderProcessInput(self.dec) } {
+                s::BasicInfo => {
+                    check_dec_status(unsafe {
+                        JxlDecoderGetBasicInfo(self.dec, basic_info.as_mut_ptr())
+                    })?;
+
+                    if let Some(pr) = self.parallel_runner {
+                        pr.callback_basic_info(unsafe { &*basic_info.as_ptr() });
+                    }
+                }
+                s::NeedPreviewOutBuffer => {
+                    let mut size = 0;
+                    check_dec_status(unsafe {
+                        JxlDecoderPreviewOutBufferSize(self.dec, &format, &mut size)
+                    })?;
+                    buffer.resize(size, 0);
+                    check_dec_status(unsafe {
+                        JxlDecoderSetPreviewOutBuffer(
+                            self.dec,
+                            &format,
+                            buffer.as_mut_ptr().cast(),
+                            size,
+                        )
+                    })?;
+                }
+                s::PreviewImage => {
+                    let info = unsafe { basic_info.assume_init_ref() };
+                    return Ok(oriented_size(
+                        info.preview.xsize,
+                        info.preview.ysize,
+                        orientation,
+                    ));
+                }
+                s::NeedImageOutBuffer => {
+                    let mut size = 0;
+                    check_dec_status(unsafe {
+                        JxlDecoderImageOutBufferSize(self.dec, &format, &mut size)
+                    })?;
+                    buffer.resize(size, 0);
+                    check_dec_status(unsafe {
+                        JxlDecoderSetImageOutBuffer(
+                            self.dec,
+                            &format,
+                            buffer.as_mut_ptr().cast(),
+                            size,
+                        )
+                    })?;
+                }
+                // Keep decoding if nothing could be rendered yet
+                s::FrameProgression if unsafe { JxlDecoderFlushImage(self.dec) } != s::Success => {}
+                s::FrameProgression | s::FullImage => {
+                    let info = unsafe { basic_info.assume_init_ref() };
+                    return Ok((info.xsize, info.ysize));
+                }
+                s::NeedMoreInput | s::Error => return Err(DecodeError::GenericError),
+                status => return Err(DecodeError::UnknownStatus(status)),
+            }
+        }
+    }
+}
+
+/// Box filter an RGB8 image down to fit in `max_edge`, keeping the aspect ratio
+fn downscale(src: &[u8], width: u32, height: u32, max_edge: u32) -> Thumbnail {
+    let longest = width.max(height);
+    if longest <= max_edge {
+        return Thumbnail {
+            width,
+            height,
+            data: src.to_vec(),
+        };
+    }
+
+    let scaled = |v: u32| {
+        let v = u64::from(v) * u64::from(max_edge) / u64::from(longest);
+        u32::try_from(v).unwrap_or(max_edge).max(1)
+    };
+    let (out_width, out_height) = (scaled(width), scaled(height));
+    let (width, height) = (width as usize, height as usize);
+    let (out_w, out_h) = (out_width as usize, out_height as usize);
+
+    let mut data = Vec::with_capacity(out_w * out_h * 3);
+    for oy in 0..out_h {
+        let (y0, y1) = (oy * height / out_h, (oy + 1) * height / out_h);
+        for ox in 0..out_w {
+            let (x0, x1) = (ox * width / out_w, (ox + 1) * width / out_w);
+
+            let mut sum = [0usize; 3];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let i = (y * width + x) * 3;
+                    for (s, &v) in sum.iter_mut().zip(&src[i..i + 3]) {
+                        *s += usize::from(v);
+                    }
+                }
+            }
+
+            let count = (y1 - y0) * (x1 - x0);
+            data.extend(sum.map(|s| u8::try_from((s + count / 2) / count).unwrap_or(u8::MAX)));
+        }
+    }
+
+    Thumbnail {
+        width: out_width,
+        height: out_height,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_downscale() {
+        // 4x2 image, left half black, right half white
+        let src: Vec<u8> = (0..8)
+            .flat_map(|i| if i % 4 < 2 { [0; 3] } else { [255; 3] })
+            .collect();
+
+        let thumb = downscale(&src, 4, 2, 2);
+        assert_eq!((thumb.width, thumb.height), (2, 1));
+        assert_eq!(thumb.data, [0, 0, 0, 255, 255, 255]);
+
+        let thumb = downscale(&src, 4, 2, 1);
+        assert_eq!((thumb.width, thumb.height), (1, 1));
+        assert_eq!(thumb.data, [128, 128, 128]);
+
+        let thumb = downscale(&src, 4, 2, 8);
+        assert_eq!((thumb.width, thumb.height), (4, 2));
+        assert_eq!(thumb.data, src);
+    }
+}
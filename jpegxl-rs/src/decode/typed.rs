@@ -0,0 +1,237 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Decoder with the output pixel type fixed at compile time
+
+use std::{io::Read, marker::PhantomData};
+
+use super::{Completeness, Frames, JxlDecoder, JxlDecoderBuilder, Metadata, Rows};
+use crate::{common::PixelType, DecodeError};
+
+impl<'pr, 'mm> JxlDecoderBuilder<'pr, 'mm> {
+    /// Fix the pixel type of the decoder output, see [`TypedDecoder`]
+    pub fn pixel_type<T: PixelType>(&mut self) -> TypedDecoderBuilder<'pr, 'mm, T> {
+        TypedDecoderBuilder {
+            builder: self.clone(),
+            _pixel_type: PhantomData,
+        }
+    }
+}
+
+/// Builder for [`TypedDecoder`], created with [`JxlDecoderBuilder::pixel_type`]
+#[derive(Clone)]
+pub struct TypedDecoderBuilder<'pr, 'mm, T: PixelType> {
+    builder: JxlDecoderBuilder<'pr, 'mm>,
+    _pixel_type: PhantomData<T>,
+}
+
+impl<'pr, 'mm, T: PixelType> TypedDecoderBuilder<'pr, 'mm, T> {
+    /// Build a [`TypedDecoder`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::CannotCreateDecoder`] if it fails to create the decoder.
+    pub fn build(&self) -> Result<TypedDecoder<'pr, 'mm, T>, DecodeError> {
+        Ok(TypedDecoder {
+            decoder: self.builder.build()?,
+            _pixel_type: PhantomData,
+        })
+    }
+}
+
+/// Decoder which always outputs pixels of type `T`
+///
+/// Only the methods decoding to pixels of type `T` are available. The options are set on
+/// the builder, and [`into_inner`](Self::into_inner) gives back the untyped [`JxlDecoder`].
+///
+/// # Example
+/// ```
+/// # use jpegxl_rs::decoder_builder;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let sample = include_bytes!("../../../samples/sample.jxl");
+/// let decoder = decoder_builder().pixel_type::<u16>().build()?;
+/// let (metadata, pixels): (_, Vec<u16>) = decoder.decode(sample)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TypedDecoder<'pr, 'mm, T: PixelType> {
+    decoder: JxlDecoder<'pr, 'mm>,
+    _pixel_type: PhantomData<T>,
+}
+
+impl<'pr, 'mm, T: PixelType> TypedDecoder<'pr, 'mm, T> {
+    /// Decode a JPEG XL image
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode(&self, data: &[u8]) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decoder.decode_with::<T>(data)
+    }
+
+    /// Decode a JPEG XL image to the raw bytes of the samples,
+    /// see [`JxlDecoder::decode_bytes_with`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode_bytes(&self, data: &[u8]) -> Result<(Metadata, Vec<u8>), DecodeError> {
+        self.decoder.decode_bytes_with::<T>(data)
+    }
+
+    /// Decode a JPEG XL image read from `reader`, see [`JxlDecoder::decode_reader`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when reading fails, or another [`DecodeError`] when the
+    /// internal decoder fails
+    pub fn decode_reader(&self, reader: impl Read) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decoder.decode_reader_with::<T>(reader)
+    }
+
+    /// Decode a JPEG XL image, tolerating truncated input,
+    /// see [`JxlDecoder::decode_lenient_with`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails, or when the input ends
+    /// before any pixels could be decoded
+    pub fn decode_lenient(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Vec<T>, Completeness), DecodeError> {
+        self.decoder.decode_lenient_with::<T>(data)
+    }
+
+    /// Decode a JPEG XL image with each channel in its own plane,
+    /// see [`JxlDecoder::decode_planar_with`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode_planar(&self, data: &[u8]) -> Result<(Metadata, Vec<Vec<T>>), DecodeError>
+    where
+        T: Copy,
+    {
+        self.decoder.decode_planar_with::<T>(data)
+    }
+
+    /// Decode a JPEG XL image into an existing surface,
+    /// see [`JxlDecoder::decode_into_surface`]
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoder fails
+    pub fn decode_into_surface(
+        &self,
+        data: &[u8],
+        surface: &mut [u8],
+        stride: usize,
+    ) -> Result<Metadata, DecodeError> {
+        self.decoder.decode_into_surface::<T>(data, surface, stride)
+    }
+
+    /// Decode a JPEG XL image a few rows at a time, see [`JxlDecoder::decode_rows`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the basic information cannot be decoded
+    pub fn decode_rows<'a>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Rows<'a, 'pr, 'mm, T>, DecodeError> {
+        self.decoder.decode_rows::<T>(data)
+    }
+
+    /// Decode the frames one at a time, see [`JxlDecoder::frames`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::InvalidInput`] if the input is not a JPEG XL image, or a
+    /// [`DecodeError`] when the decoder fails to set up
+    pub fn frames<'a>(
+        &'a mut self,
+        data: &'a [u8],
+    ) -> Result<Frames<'a, 'pr, 'mm, T>, DecodeError> {
+        self.decoder.frames::<T>(data)
+    }
+
+    /// Decode a JPEG XL file, see [`JxlDecoder::decode_file_with`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be mapped, or a [`DecodeError`]
+    /// when internal decoder fails
+    #[cfg(feature = "memmap2")]
+    pub fn decode_file(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decoder.decode_file_with::<T>(path)
+    }
+
+    /// Decode a JPEG XL image in a new file at `path`, see [`JxlDecoder::decode_to_file`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be created or mapped, or a
+    /// [`DecodeError`] when internal decoder fails
+    #[cfg(feature = "memmap2")]
+    pub fn decode_to_file(
+        &mut self,
+        data: &[u8],
+        path: impl AsRef<std::path::Path>,
+        format: crate::FileFormat,
+    ) -> Result<Metadata, DecodeError> {
+        self.decoder.decode_to_file::<T>(data, path, format)
+    }
+
+    /// Unwrap the inner [`JxlDecoder`]
+    #[must_use]
+    pub fn into_inner(self) -> JxlDecoder<'pr, 'mm> {
+        self.decoder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use half::f16;
+    use testresult::TestResult;
+
+    use crate::{decoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn typed() -> TestResult {
+        let mut builder = decoder_builder();
+        let decoder = builder.icc_profile(true).pixel_type::<f16>().build()?;
+        let (metadata, pixels) = decoder.decode(SAMPLE_JXL)?;
+        assert!(metadata.icc_profile.is_some());
+        assert_eq!(
+            pixels.len(),
+            (metadata.width * metadata.height * 4) as usize
+        );
+
+        let mut decoder = builder.icc_profile(false).pixel_type::<f16>().build()?;
+        let (metadata, planes) = decoder.decode_planar(SAMPLE_JXL)?;
+        assert!(metadata.icc_profile.is_none());
+        assert_eq!(planes.len(), 4);
+
+        let (_, read) = decoder.decode_reader(SAMPLE_JXL)?;
+        assert_eq!(read, decoder.decode(SAMPLE_JXL)?.1);
+        let rows = decoder.decode_rows(SAMPLE_JXL)?;
+        let samples: usize = rows
+            .map(|run| run.map(|run| run.data.len()))
+            .sum::<Result<_, _>>()?;
+        assert_eq!(samples, read.len());
+
+        let decoder = decoder.into_inner();
+        decoder.decode_with::<u8>(SAMPLE_JXL)?;
+
+        Ok(())
+    }
+}
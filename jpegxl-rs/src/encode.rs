@@ -17,13 +17,24 @@ along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 
 //! Encoder of JPEG XL format
 
-use std::{marker::PhantomData, mem::MaybeUninit, ops::Deref, ptr::null};
+use std::{
+    cell::Cell, io::Write, marker::PhantomData, mem::MaybeUninit, ops::Deref, ptr::null, sync::Arc,
+};
 
 #[allow(clippy::wildcard_imports)]
-use jpegxl_sys::encode::*;
+use jpegxl_sys::{
+    cms::JxlGetDefaultCms,
+    encode::*,
+    types::{JxlBitDepth, JxlBitDepthType},
+};
 
 use crate::{
-    common::PixelType, errors::EncodeError, memory::MemoryManager, parallel::JxlParallelRunner,
+    cms::{self, ColorManagement},
+    common::{CancellationToken, PixelType},
+    errors::EncodeError,
+    icc::{IccColorSpace, IccSummary},
+    memory::MemoryManager,
+    parallel::JxlParallelRunner,
 };
 
 mod options;
@@ -35,6 +46,15 @@ pub use metadata::*;
 mod frame;
 pub use frame::*;
 
+mod batch;
+pub use batch::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod send;
+pub use send::*;
+
 // MARK: Utility types
 
 /// Encoder result
@@ -55,6 +75,10 @@ impl<U: PixelType> Deref for EncoderResult<U> {
 // MARK: Encoder
 
 /// JPEG XL Encoder
+///
+/// Like the decoder, an encoder is neither [`Send`] nor [`Sync`], since the parallel
+/// runner and memory manager it refers to might not be. Build a [`SendEncoder`] with
+/// [`JxlEncoderBuilder::build_send`] to move one to another thread.
 #[derive(Builder)]
 #[builder(build_fn(skip, error = "None"))]
 #[builder(setter(strip_option))]
@@ -118,6 +142,11 @@ pub struct JxlEncoder<'prl, 'mm> {
     /// Default: SRGB
     pub color_encoding: ColorEncoding,
 
+    /// Set an ICC profile describing the input pixels, used instead of `color_encoding`
+    ///
+    /// Default: `None`
+    pub icc_profile: Option<Vec<u8>>,
+
     /// Set parallel runner
     ///
     /// Default: `None`, indicating single thread execution
@@ -129,6 +158,21 @@ pub struct JxlEncoder<'prl, 'mm> {
     /// Set memory manager
     #[allow(dead_code)]
     memory_manager: Option<&'mm dyn MemoryManager>,
+
+    /// Set a token to cancel encoding with [`EncodeError::Cancelled`]
+    ///
+    /// Default: `None`, and encoding always runs to the end
+    pub cancellation: Option<CancellationToken>,
+
+    /// Set the color management system used to convert the input to the internal color space
+    ///
+    /// Default: `None`, and the color management system of libjxl is used
+    pub cms: Option<Arc<dyn ColorManagement>>,
+
+    /// Color management system handed to libjxl, boxed to keep its address when the
+    /// encoder is moved or `cms` is changed
+    #[builder(setter(skip))]
+    installed_cms: Cell<Option<Box<Arc<dyn ColorManagement>>>>,
 }
 
 impl<'prl, 'mm> JxlEncoderBuilder<'prl, 'mm> {
@@ -150,12 +194,20 @@ impl<'prl, 'mm> JxlEncoderBuilder<'prl, 'mm> {
         }
 
         let options_ptr = unsafe { JxlEncoderFrameSettingsCreate(enc, null()) };
+        Ok(self.wrap(enc, options_ptr))
+    }
 
+    // Wrap the underlying encoder with the options of the builder
+    fn wrap(
+        &self,
+        enc: *mut jpegxl_sys::encode::JxlEncoder,
+        options_ptr: *mut JxlEncoderFrameSettings,
+    ) -> JxlEncoder<'prl, 'mm> {
         let init_buffer_size =
             self.init_buffer_size
-                .map_or(512 * 1024 * 1024, |v| if v < 32 { 32 } else { v });
+                .map_or(512 * 1024, |v| if v < 32 { 32 } else { v });
 
-        Ok(JxlEncoder {
+        JxlEncoder {
             enc,
             options_ptr,
             has_alpha: self.has_alpha.unwrap_or_default(),
@@ -167,17 +219,20 @@ impl<'prl, 'mm> JxlEncoderBuilder<'prl, 'mm> {
             decoding_speed: self.decoding_speed.unwrap_or_default(),
             init_buffer_size,
             color_encoding: self.color_encoding.unwrap_or(ColorEncoding::Srgb),
+            icc_profile: self.icc_profile.clone().flatten(),
             parallel_runner: self.parallel_runner.flatten(),
             use_box: self.use_box.unwrap_or_default(),
-            memory_manager: mm,
-        })
+            memory_manager: self.memory_manager.flatten(),
+            cancellation: self.cancellation.clone().flatten(),
+            cms: self.cms.clone().flatten(),
+            installed_cms: Cell::default(),
+        }
     }
 
     /// Set the `quality` parameter from a JPEG-style quality factor (0-100, higher is better
     /// quality).
     pub fn jpeg_quality(&mut self, quality: f32) -> &mut Self {
-        // SAFETY: the C API has no safety requirements.
-        self.quality = Some(unsafe { JxlEncoderDistanceFromQuality(quality) });
+        self.quality = Some(distance_from_quality(quality));
         self
     }
 }
@@ -204,6 +259,14 @@ impl JxlEncoder<'_, '_> {
 
     // Set options
     fn set_options(&self) -> Result<(), EncodeError> {
+        // libjxl keeps the color management system across resets, so it is always set
+        let installed = self.cms.clone().map(Box::new);
+        let interface = installed
+            .as_deref()
+            .map_or_else(|| unsafe { (*JxlGetDefaultCms()).clone() }, cms::interface);
+        unsafe { JxlEncoderSetCms(self.enc, interface) };
+        // The previous one is no longer referred to by libjxl
+        drop(self.installed_cms.replace(installed));
         self.check_enc_status(unsafe { JxlEncoderUseContainer(self.enc, self.use_container) })?;
         self.check_enc_status(unsafe {
             JxlEncoderSetFrameLossless(self.options_ptr, self.lossless)
@@ -230,6 +293,10 @@ impl JxlEncoder<'_, '_> {
     }
 
     // Setup the encoder
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip(self), err(Debug))
+    )]
     fn setup_encoder(
         &self,
         width: u32,
@@ -273,11 +340,12 @@ impl JxlEncoder<'_, '_> {
             basic_info.alpha_exponent_bits = 0;
         }
 
-        match self.color_encoding {
-            ColorEncoding::SrgbLuma | ColorEncoding::LinearSrgbLuma => {
-                basic_info.num_color_channels = 1;
-            }
-            _ => (),
+        let is_luma = self.icc_profile.as_ref().map_or_else(
+            || self.color_encoding.is_luma(),
+            |icc| IccSummary::parse(icc).is_some_and(|s| s.color_space == IccColorSpace::Gray),
+        );
+        if is_luma {
+            basic_info.num_color_channels = 1;
         }
 
         if let Some(pr) = self.parallel_runner {
@@ -286,17 +354,31 @@ impl JxlEncoder<'_, '_> {
 
         self.check_enc_status(unsafe { JxlEncoderSetBasicInfo(self.enc, &basic_info) })?;
 
+        if let Some(icc) = &self.icc_profile {
+            return self.check_enc_status(unsafe {
+                JxlEncoderSetICCProfile(self.enc, icc.as_ptr(), icc.len())
+            });
+        }
         self.check_enc_status(unsafe {
             JxlEncoderSetColorEncoding(self.enc, &self.color_encoding.into())
         })
     }
 
     // Add a frame
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(len = std::mem::size_of_val(frame.data)),
+            err(Debug)
+        )
+    )]
     fn add_frame<T: PixelType>(&self, frame: &EncoderFrame<T>) -> Result<(), EncodeError> {
         self.check_enc_status(unsafe {
             JxlEncoderAddImageFrame(
                 self.options_ptr,
-                &frame.pixel_format(),
+                &frame.jxl_pixel_format(),
                 frame.data.as_ptr().cast(),
                 std::mem::size_of_val(frame.data),
             )
@@ -304,6 +386,10 @@ impl JxlEncoder<'_, '_> {
     }
 
     // Add a frame from JPEG raw data
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(len = data.len()), err(Debug))
+    )]
     fn add_jpeg_frame(&self, data: &[u8]) -> Result<(), EncodeError> {
         self.check_enc_status(unsafe {
             JxlEncoderAddJPEGFrame(
@@ -314,44 +400,101 @@ impl JxlEncoder<'_, '_> {
         })
     }
 
-    fn _internal(&mut self) -> Result<Vec<u8>, EncodeError> {
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(len), err(Debug))
+    )]
+    fn process_output(&mut self) -> Result<Vec<u8>, EncodeError> {
         unsafe { JxlEncoderCloseInput(self.enc) };
 
-        let mut buffer = vec![0; self.init_buffer_size];
-        let mut next_out = buffer.as_mut_ptr().cast();
-        let mut avail_out = buffer.len();
+        let mut buffer = Vec::<u8>::with_capacity(self.init_buffer_size);
+        self.write_output(&mut buffer)?;
+        self.reset();
+        #[cfg(feature = "trace")]
+        tracing::Span::current().record("len", buffer.len());
+
+        Ok(buffer)
+    }
+
+    // Compress the queued input, appending the output to `buffer`. Write into the spare
+    // capacity, so growing only moves the bytes written so far
+    pub(crate) fn write_output(&mut self, buffer: &mut Vec<u8>) -> Result<(), EncodeError> {
+        if buffer.len() == buffer.capacity() {
+            buffer.reserve(self.init_buffer_size);
+        }
 
-        let mut status;
         loop {
-            status = unsafe { JxlEncoderProcessOutput(self.enc, &mut next_out, &mut avail_out) };
+            self.check_cancelled()?;
+
+            let mut next_out = unsafe { buffer.as_mut_ptr().add(buffer.len()) };
+            let mut avail_out = buffer.capacity() - buffer.len();
+            let status =
+                unsafe { JxlEncoderProcessOutput(self.enc, &mut next_out, &mut avail_out) };
+
+            let written = buffer.capacity() - avail_out;
+            unsafe { buffer.set_len(written) };
 
             if status != JxlEncoderStatus::NeedMoreOutput {
-                break;
+                return self.check_enc_status(status);
             }
 
-            unsafe {
-                let offset = next_out.offset_from(buffer.as_ptr());
-                debug_assert!(offset >= 0);
+            buffer.reserve(buffer.capacity());
+            #[cfg(feature = "trace")]
+            tracing::trace!(written, capacity = buffer.capacity(), "grow output");
+        }
+    }
+
+    // Compress the input after closing it, handing the output to `writer` in chunks of the
+    // initial buffer size as they are produced
+    pub(crate) fn process_output_to(&mut self, writer: &mut dyn Write) -> Result<(), EncodeError> {
+        unsafe { JxlEncoderCloseInput(self.enc) };
+
+        let mut buffer = Vec::<u8>::with_capacity(self.init_buffer_size);
+        let result = loop {
+            self.check_cancelled()?;
+
+            let mut next_out = buffer.as_mut_ptr();
+            let mut avail_out = buffer.capacity();
+            let status =
+                unsafe { JxlEncoderProcessOutput(self.enc, &mut next_out, &mut avail_out) };
+            unsafe { buffer.set_len(buffer.capacity() - avail_out) };
 
-                buffer.resize(buffer.len() * 2, 0);
-                next_out = buffer.as_mut_ptr().offset(offset);
-                avail_out = buffer.len().wrapping_add_signed(-offset);
+            if let Err(e) = writer.write_all(&buffer) {
+                break Err(e.into());
+            }
+            if status != JxlEncoderStatus::NeedMoreOutput {
+                break self.check_enc_status(status);
             }
+        };
+        self.reset();
+        result
+    }
+
+    // Reset and return `EncodeError::Cancelled` if the cancellation token is set
+    fn check_cancelled(&mut self) -> Result<(), EncodeError> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            self.reset();
+            return Err(EncodeError::Cancelled);
         }
-        buffer.truncate(next_out as usize - buffer.as_ptr() as usize);
-        self.check_enc_status(status)?;
+        Ok(())
+    }
 
+    // Drop the queued input and settings, ready for the next image
+    pub(crate) fn reset(&mut self) {
         unsafe { JxlEncoderReset(self.enc) };
         self.options_ptr = unsafe { JxlEncoderFrameSettingsCreate(self.enc, null()) };
-
-        buffer.shrink_to_fit();
-        Ok(buffer)
+        // Boxes have to be enabled again for the metadata of the next image
+        self.use_box = false;
     }
 
     // Start encoding
     fn start_encoding<U: PixelType>(&mut self) -> Result<EncoderResult<U>, EncodeError> {
         Ok(EncoderResult {
-            data: self._internal()?,
+            data: self.process_output()?,
             _pixel_type: PhantomData,
         })
     }
@@ -386,6 +529,19 @@ impl<'prl, 'mm> JxlEncoder<'prl, 'mm> {
         Ok(MultiFrames::<'enc, 'prl, 'mm, U>(self, PhantomData))
     }
 
+    /// Return a [`Pipeline`] to add multiple frames prepared on another thread
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if it fails to set up the encoder
+    pub fn pipeline<'enc, U: PixelType>(
+        &'enc mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<Pipeline<'enc, 'prl, 'mm, U>, EncodeError> {
+        self.setup_encoder(width, height, U::bits_per_sample(), self.has_alpha)?;
+        Ok(Pipeline::new(self))
+    }
+
     /// Add a metadata box to the encoder
     ///
     /// # Errors
@@ -471,6 +627,42 @@ impl<'prl, 'mm> JxlEncoder<'prl, 'mm> {
         self.add_frame(frame)?;
         self.start_encoding::<U>()
     }
+
+    /// Encode a JPEG XL image from a frame of integer samples using only the lower
+    /// `bits_per_sample` bits, e.g. 10-bit samples stored in `u16`.
+    ///
+    /// [`encode_frame`](Self::encode_frame) infers the bit depth from `U` instead.
+    ///
+    /// # Errors
+    /// Return [`EncodeError::NotSupported`] if `bits_per_sample` is 0, or exceeds the bits
+    /// of `T` or `U`, or either is a floating point type.
+    /// Return other [`EncodeError`] if the internal encoder fails to encode
+    pub fn encode_frame_with_bit_depth<T: PixelType, U: PixelType>(
+        &mut self,
+        frame: &EncoderFrame<T>,
+        width: u32,
+        height: u32,
+        bits_per_sample: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        let fits = |(bits, exp): (u32, u32)| exp == 0 && bits >= bits_per_sample;
+        if bits_per_sample == 0 || !fits(T::bits_per_sample()) || !fits(U::bits_per_sample()) {
+            return Err(EncodeError::NotSupported);
+        }
+
+        self.setup_encoder(width, height, (bits_per_sample, 0), self.has_alpha)?;
+        self.check_enc_status(unsafe {
+            JxlEncoderSetFrameBitDepth(
+                self.options_ptr,
+                &JxlBitDepth {
+                    type_: JxlBitDepthType::BitDepthFromCodestream,
+                    bits_per_sample: 0,
+                    exponent_bits_per_sample: 0,
+                },
+            )
+        })?;
+        self.add_frame(frame)?;
+        self.start_encoding::<U>()
+    }
 }
 
 impl Drop for JxlEncoder<'_, '_> {
@@ -485,6 +677,34 @@ pub fn encoder_builder<'prl, 'mm>() -> JxlEncoderBuilder<'prl, 'mm> {
     JxlEncoderBuilder::default()
 }
 
+/// Convert a JPEG-style quality factor (0-100, higher is better quality) to the distance
+/// of [`JxlEncoder::quality`], as libjxl and `cjxl` do
+#[must_use]
+pub fn distance_from_quality(quality: f32) -> f32 {
+    // SAFETY: the C API has no safety requirements.
+    unsafe { JxlEncoderDistanceFromQuality(quality) }
+}
+
+/// Convert a distance to the quality factor mapped to it by [`distance_from_quality`]
+///
+/// Distances below the one of quality 100 map to 100, and above the one of quality 0 to 0.
+#[must_use]
+pub fn quality_from_distance(distance: f32) -> f32 {
+    // Inverse of the two pieces of the libjxl mapping, joining at quality 30
+    const A: f32 = 53.0 / 3000.0;
+    const B: f32 = -23.0 / 20.0;
+    if distance <= 0.1 {
+        100.0
+    } else if distance <= 6.4 {
+        100.0 - (distance - 0.1) / 0.09
+    } else if distance < 25.0 {
+        // Smaller root of `A * q^2 + B * q + 25 = distance`, where the curve decreases
+        (-B - (B * B - 4.0 * A * (25.0 - distance)).sqrt()) / (2.0 * A)
+    } else {
+        0.0
+    }
+}
+
 // MARK: Tests
 #[cfg(test)]
 mod tests {
@@ -511,4 +731,15 @@ mod tests {
         assert!(encoder.use_box);
         Ok(())
     }
+
+    #[test]
+    fn test_quality_mapping() {
+        assert!(distance_from_quality(100.0).abs() < f32::EPSILON);
+        for quality in (0..100u8).map(f32::from) {
+            let back = quality_from_distance(distance_from_quality(quality));
+            assert!((back - quality).abs() < 1e-3, "{quality} -> {back}");
+        }
+        assert!((quality_from_distance(0.0) - 100.0).abs() < f32::EPSILON);
+        assert!(quality_from_distance(30.0).abs() < f32::EPSILON);
+    }
 }
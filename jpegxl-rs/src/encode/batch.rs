@@ -0,0 +1,147 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Mutex, PoisonError},
+    thread,
+};
+
+use crate::{common::PixelType, EncodeError};
+
+use super::{EncoderFrame, EncoderResult, JxlEncoder};
+
+/// An image for [`encode_batch`]
+pub struct BatchItem<'data, T: PixelType> {
+    /// Pixels of the image
+    pub frame: EncoderFrame<'data, T>,
+    /// Width of the image
+    pub width: u32,
+    /// Height of the image
+    pub height: u32,
+}
+
+/// Encode many images on `num_threads` worker threads, or one per available core
+///
+/// Every worker builds its own encoder with `make_encoder` and reuses it for the images
+/// it takes, starting each output buffer at the size of its largest output so far. After a
+/// failure, the worker builds a new encoder for its next image.
+///
+/// Give the encoders a shared runner, e.g. a `SharedRunner`, to bound the total number of
+/// threads, or none to encode each image on its worker alone.
+///
+/// Results are returned in the order of `items`, one per image.
+///
+/// # Example
+/// ```
+/// # use jpegxl_rs::{encode::{encode_batch, BatchItem, EncoderFrame, EncoderResult}, encoder_builder};
+/// let images = vec![vec![0u8; 3 * 4]; 8];
+/// let items = images.iter().map(|image| BatchItem {
+///     frame: EncoderFrame::new(image),
+///     width: 2,
+///     height: 2,
+/// });
+/// let results: Vec<Result<EncoderResult<u8>, _>> =
+///     encode_batch(items, None, || encoder_builder().build());
+/// assert!(results.iter().all(Result::is_ok));
+/// ```
+pub fn encode_batch<'data, 'prl, 'mm, T, U, I, F>(
+    items: I,
+    num_threads: Option<NonZeroUsize>,
+    make_encoder: F,
+) -> Vec<Result<EncoderResult<U>, EncodeError>>
+where
+    T: PixelType + Sync + 'data,
+    U: PixelType + Send,
+    I: IntoIterator<Item = BatchItem<'data, T>>,
+    I::IntoIter: Send,
+    F: Fn() -> Result<JxlEncoder<'prl, 'mm>, EncodeError> + Sync,
+{
+    let num_threads = num_threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let items = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::new());
+    // The lock is never held while encoding, so a panic can not leave it inconsistent
+    let next = || items.lock().unwrap_or_else(PoisonError::into_inner).next();
+    let push = |index, result| {
+        results
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((index, result));
+    };
+
+    thread::scope(|s| {
+        for _ in 0..num_threads {
+            s.spawn(|| {
+                let mut encoder = None;
+                let mut buffer_size = 0;
+                while let Some((index, item)) = next() {
+                    let result = match encoder.take().map_or_else(&make_encoder, Ok) {
+                        Ok(mut enc) => {
+                            enc.init_buffer_size = enc.init_buffer_size.max(buffer_size);
+                            let result = enc.encode_frame(&item.frame, item.width, item.height);
+                            // An encoder is left in an unknown state by a failure
+                            if let Ok(result) = &result {
+                                buffer_size = buffer_size.max(result.data.len());
+                                encoder = Some(enc);
+                            }
+                            result
+                        }
+                        Err(e) => Err(e),
+                    };
+                    push(index, result);
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_else(PoisonError::into_inner);
+    results.sort_unstable_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{decoder_builder, encoder_builder};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_encode_batch() -> TestResult {
+        let images: Vec<Vec<u8>> = (0..16u8).map(|i| vec![i; 3 * 4 * 4]).collect();
+        let items = images.iter().map(|image| BatchItem {
+            frame: EncoderFrame::new(image),
+            width: 4,
+            height: 4,
+        });
+        let results: Vec<Result<EncoderResult<u8>, _>> =
+            encode_batch(items, NonZeroUsize::new(3), || {
+                encoder_builder()
+                    .lossless(true)
+                    .uses_original_profile(true)
+                    .build()
+            });
+
+        let decoder = decoder_builder().build()?;
+        assert_eq!(results.len(), images.len());
+        for (image, result) in images.iter().zip(results) {
+            assert_eq!(&decoder.decode_with::<u8>(&result?)?.1, image);
+        }
+
+        // A failing image does not stop the others
+        let bad = [0u8; 2];
+        let items = [&images[0][..], &bad[..]]
+            .into_iter()
+            .map(|image| BatchItem {
+                frame: EncoderFrame::new(image),
+                width: 4,
+                height: 4,
+            });
+        let results: Vec<Result<EncoderResult<u8>, _>> =
+            encode_batch(items, None, || encoder_builder().build());
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
-use std::marker::PhantomData;
+use std::{io::Write, marker::PhantomData};
 
 use jpegxl_sys::types::{JxlEndianness, JxlPixelFormat};
 
-use crate::{common::PixelType, EncodeError};
+use crate::{
+    common::{PixelFormat, PixelType},
+    EncodeError,
+};
 
 use super::{EncoderResult, JxlEncoder};
 
@@ -10,9 +13,7 @@ use super::{EncoderResult, JxlEncoder};
 #[allow(clippy::module_name_repetitions)]
 pub struct EncoderFrame<'data, T: PixelType> {
     pub(crate) data: &'data [T],
-    num_channels: Option<u32>,
-    endianness: Option<JxlEndianness>,
-    align: Option<usize>,
+    format: PixelFormat,
 }
 
 impl<'data, T: PixelType> EncoderFrame<'data, T> {
@@ -22,9 +23,7 @@ impl<'data, T: PixelType> EncoderFrame<'data, T> {
     pub fn new(data: &'data [T]) -> Self {
         Self {
             data,
-            num_channels: None,
-            endianness: None,
-            align: None,
+            format: PixelFormat::default(),
         }
     }
 
@@ -33,14 +32,14 @@ impl<'data, T: PixelType> EncoderFrame<'data, T> {
     /// _Note_: If you want to use alpha channel, add here
     #[must_use]
     pub fn num_channels(mut self, value: u32) -> Self {
-        self.num_channels = Some(value);
+        self.format.num_channels = value;
         self
     }
 
     /// Set the endianness of the source.
     #[must_use]
     pub fn endianness(mut self, value: JxlEndianness) -> Self {
-        self.endianness = Some(value);
+        self.format.endianness = value;
         self
     }
 
@@ -48,17 +47,24 @@ impl<'data, T: PixelType> EncoderFrame<'data, T> {
     /// Align scanlines to a multiple of align bytes, or 0 to require no alignment at all
     #[must_use]
     pub fn align(mut self, value: usize) -> Self {
-        self.align = Some(value);
+        self.format.align = value;
         self
     }
 
-    pub(crate) fn pixel_format(&self) -> JxlPixelFormat {
-        JxlPixelFormat {
-            num_channels: self.num_channels.unwrap_or(3),
-            data_type: T::pixel_type(),
-            endianness: self.endianness.unwrap_or(JxlEndianness::Native),
-            align: self.align.unwrap_or(0),
-        }
+    /// Set the whole pixel format of the source.
+    ///
+    /// The data type is always the one of `T`
+    #[must_use]
+    pub fn pixel_format(mut self, value: PixelFormat) -> Self {
+        self.format = PixelFormat {
+            data_type: None,
+            ..value
+        };
+        self
+    }
+
+    pub(crate) fn jxl_pixel_format(&self) -> JxlPixelFormat {
+        self.format.to_jxl(3, T::pixel_type())
     }
 }
 
@@ -94,4 +100,13 @@ impl<U: PixelType> MultiFrames<'_, '_, '_, U> {
     pub fn encode(self) -> Result<EncoderResult<U>, EncodeError> {
         self.0.start_encoding()
     }
+
+    /// Encode a JPEG XL image from the frames, writing the output to `writer` in chunks
+    /// of the encoder's `init_buffer_size` as they are compressed
+    /// # Errors
+    /// Return [`EncodeError::Io`] if writing fails, or another [`EncodeError`] if the
+    /// internal encoder fails to encode
+    pub fn encode_to(self, mut writer: impl Write) -> Result<(), EncodeError> {
+        self.0.process_output_to(&mut writer)
+    }
 }
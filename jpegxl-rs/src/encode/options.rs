@@ -1,9 +1,12 @@
 use std::mem::MaybeUninit;
 
-use jpegxl_sys::{color_encoding::JxlColorEncoding, encode as api};
+use jpegxl_sys::{
+    color_encoding::{JxlColorEncoding, JxlPrimaries, JxlTransferFunction, JxlWhitePoint},
+    encode as api,
+};
 
 /// Encoding speed
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum EncoderSpeed {
     /// Fastest, 1
     Lightning = 1,
@@ -18,6 +21,7 @@ pub enum EncoderSpeed {
     /// 6
     Wombat,
     /// 7, default
+    #[default]
     Squirrel,
     /// 8
     Kitten,
@@ -27,13 +31,11 @@ pub enum EncoderSpeed {
     Glacier,
 }
 
-impl std::default::Default for EncoderSpeed {
-    fn default() -> Self {
-        Self::Squirrel
-    }
-}
-
 /// Encoding color profile
+///
+/// Besides sRGB, presets for common wide gamut and video color spaces are provided, each with
+/// a linear variant. They are written to the image as an enumerated color encoding, and
+/// don't need an ICC profile.
 #[derive(Debug, Clone, Copy)]
 pub enum ColorEncoding {
     /// SRGB, default for uint pixel types
@@ -44,26 +46,121 @@ pub enum ColorEncoding {
     SrgbLuma,
     /// Linear SRGB with only luma channel
     LinearSrgbLuma,
+    /// Display P3, DCI-P3 primaries with D65 white point and the sRGB transfer function
+    DisplayP3,
+    /// Display P3 primaries with linear transfer function
+    LinearDisplayP3,
+    /// Rec. 709 (BT.709), sRGB primaries with the BT.709 transfer function
+    Rec709,
+    /// Rec. 2020 (BT.2020), with the BT.709 transfer function
+    Rec2020,
+    /// Rec. 2020 primaries with linear transfer function
+    LinearRec2020,
+    /// Rec. 2100 PQ, Rec. 2020 primaries with the perceptual quantizer for HDR
+    Rec2100Pq,
+    /// Rec. 2100 HLG, Rec. 2020 primaries with hybrid log-gamma for HDR
+    Rec2100Hlg,
+    /// `ProPhoto` RGB (ROMM RGB), with D50 white point and gamma 1.8
+    ProPhoto,
+    /// `ProPhoto` RGB primaries with linear transfer function
+    LinearProPhoto,
+}
+
+impl ColorEncoding {
+    /// Whether the color space has only a luma channel
+    pub(crate) fn is_luma(self) -> bool {
+        matches!(self, Self::SrgbLuma | Self::LinearSrgbLuma)
+    }
 }
 
+/// D50 white point of `ProPhoto` RGB
+const PROPHOTO_WHITE: [f64; 2] = [0.3457, 0.3585];
+/// Red, green and blue primaries of `ProPhoto` RGB
+const PROPHOTO_PRIMARIES: [[f64; 2]; 3] = [[0.7347, 0.2653], [0.1596, 0.8404], [0.0366, 0.0001]];
+
 impl From<ColorEncoding> for JxlColorEncoding {
     fn from(val: ColorEncoding) -> Self {
-        use ColorEncoding::{LinearSrgb, LinearSrgbLuma, Srgb, SrgbLuma};
+        use ColorEncoding::{
+            DisplayP3, LinearDisplayP3, LinearProPhoto, LinearRec2020, LinearSrgb, LinearSrgbLuma,
+            ProPhoto, Rec2020, Rec2100Hlg, Rec2100Pq, Rec709, Srgb, SrgbLuma,
+        };
 
         let mut color_encoding = MaybeUninit::uninit();
 
-        unsafe {
+        let mut color_encoding = unsafe {
             match val {
-                Srgb => api::JxlColorEncodingSetToSRGB(color_encoding.as_mut_ptr(), false),
-                LinearSrgb => {
-                    api::JxlColorEncodingSetToLinearSRGB(color_encoding.as_mut_ptr(), false);
-                }
                 SrgbLuma => api::JxlColorEncodingSetToSRGB(color_encoding.as_mut_ptr(), true),
                 LinearSrgbLuma => {
                     api::JxlColorEncodingSetToLinearSRGB(color_encoding.as_mut_ptr(), true);
                 }
+                LinearSrgb | LinearDisplayP3 | LinearRec2020 | LinearProPhoto => {
+                    api::JxlColorEncodingSetToLinearSRGB(color_encoding.as_mut_ptr(), false);
+                }
+                _ => api::JxlColorEncodingSetToSRGB(color_encoding.as_mut_ptr(), false),
             }
             color_encoding.assume_init()
+        };
+
+        // The presets only differ from sRGB in their primaries, white point and transfer
+        match val {
+            DisplayP3 | LinearDisplayP3 => color_encoding.primaries = JxlPrimaries::P3,
+            Rec2020 | LinearRec2020 | Rec2100Pq | Rec2100Hlg => {
+                color_encoding.primaries = JxlPrimaries::Rec2100;
+            }
+            ProPhoto | LinearProPhoto => {
+                color_encoding.white_point = JxlWhitePoint::Custom;
+                color_encoding.white_point_xy = PROPHOTO_WHITE;
+                color_encoding.primaries = JxlPrimaries::Custom;
+                [
+                    color_encoding.primaries_red_xy,
+                    color_encoding.primaries_green_xy,
+                    color_encoding.primaries_blue_xy,
+                ] = PROPHOTO_PRIMARIES;
+            }
+            Srgb | LinearSrgb | SrgbLuma | LinearSrgbLuma | Rec709 => {}
+        }
+        match val {
+            Rec709 | Rec2020 => color_encoding.transfer_function = JxlTransferFunction::Rec709,
+            Rec2100Pq => color_encoding.transfer_function = JxlTransferFunction::Pq,
+            Rec2100Hlg => color_encoding.transfer_function = JxlTransferFunction::Hlg,
+            ProPhoto => {
+                color_encoding.transfer_function = JxlTransferFunction::Gamma;
+                // Exponent of the encoding, the inverse of the display gamma
+                color_encoding.gamma = 1.0 / 1.8;
+            }
+            _ => {}
         }
+
+        color_encoding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_presets() {
+        let p3 = JxlColorEncoding::from(ColorEncoding::DisplayP3);
+        assert_eq!(p3.primaries, JxlPrimaries::P3);
+        assert_eq!(p3.white_point, JxlWhitePoint::D65);
+        assert_eq!(p3.transfer_function, JxlTransferFunction::SRgb);
+
+        let rec2020 = JxlColorEncoding::from(ColorEncoding::LinearRec2020);
+        assert_eq!(rec2020.primaries, JxlPrimaries::Rec2100);
+        assert_eq!(rec2020.transfer_function, JxlTransferFunction::Linear);
+
+        let pq = JxlColorEncoding::from(ColorEncoding::Rec2100Pq);
+        assert_eq!(pq.transfer_function, JxlTransferFunction::Pq);
+
+        let prophoto = JxlColorEncoding::from(ColorEncoding::ProPhoto);
+        assert_eq!(prophoto.primaries, JxlPrimaries::Custom);
+        assert_eq!(prophoto.white_point, JxlWhitePoint::Custom);
+        assert_eq!(prophoto.transfer_function, JxlTransferFunction::Gamma);
+
+        let rec709 = JxlColorEncoding::from(ColorEncoding::Rec709);
+        assert_eq!(rec709.primaries, JxlPrimaries::SRgb);
+        assert_eq!(rec709.transfer_function, JxlTransferFunction::Rec709);
     }
 }
@@ -0,0 +1,217 @@
+use std::{marker::PhantomData, sync::mpsc, thread};
+
+use jpegxl_sys::encode::JxlEncoderCloseInput;
+
+use crate::{
+    common::{PixelFormat, PixelType},
+    EncodeError,
+};
+
+use super::{EncoderFrame, EncoderResult, JxlEncoder};
+
+/// Encoder of multiple frames, preparing the next frames on another thread while the
+/// current one is compressed. Created by [`JxlEncoder::pipeline`]
+pub struct Pipeline<'enc, 'prl, 'mm, U> {
+    encoder: &'enc mut JxlEncoder<'prl, 'mm>,
+    queue_depth: usize,
+    format: PixelFormat,
+    on_frame: Option<Box<dyn FnMut(usize) + 'enc>>,
+    _pixel_type: PhantomData<U>,
+}
+
+impl<'enc, 'prl, 'mm, U: PixelType> Pipeline<'enc, 'prl, 'mm, U> {
+    pub(crate) fn new(encoder: &'enc mut JxlEncoder<'prl, 'mm>) -> Self {
+        Self {
+            encoder,
+            queue_depth: 2,
+            format: PixelFormat::default(),
+            on_frame: None,
+            _pixel_type: PhantomData,
+        }
+    }
+
+    /// Set the number of prepared frames waiting to be compressed, bounding the memory held
+    /// by the pipeline. Each frame is compressed as soon as the next one is handed over, so
+    /// at most one uncompressed frame stays queued in the encoder. With 0, the next frame is
+    /// prepared but handed over only once the current one is compressed
+    ///
+    /// Default: 2
+    #[must_use]
+    pub fn queue_depth(mut self, value: usize) -> Self {
+        self.queue_depth = value;
+        self
+    }
+
+    /// Set the pixel format of every frame, as [`EncoderFrame::pixel_format`]
+    ///
+    /// Default: RGB(3) channels, native endianness and no alignment
+    #[must_use]
+    pub fn pixel_format(mut self, value: PixelFormat) -> Self {
+        self.format = value;
+        self
+    }
+
+    /// Call `f` with the number of frames compressed so far, after each of them
+    #[must_use]
+    pub fn on_frame(mut self, f: impl FnMut(usize) + 'enc) -> Self {
+        self.on_frame = Some(Box::new(f));
+        self
+    }
+
+    /// Encode the frames yielded by `frames`, which runs on a separate thread. Do the
+    /// preparation of the pixels, e.g. swizzling or converting them, lazily in the iterator
+    /// to overlap it with the compression.
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if the internal encoder fails to add a frame or to encode, after
+    /// which no more frames are taken from `frames`
+    ///
+    /// # Example
+    /// ```
+    /// # use jpegxl_rs::encoder_builder;
+    /// # use jpegxl_rs::encode::EncoderResult;
+    /// let mut encoder = encoder_builder().build()?;
+    /// let raw_frames = vec![vec![[0u8, 0, 0, 255]; 4]; 8];
+    /// // Drop the alpha channel, on the thread of the pipeline
+    /// let frames = raw_frames
+    ///     .into_iter()
+    ///     .map(|pixels| pixels.iter().flat_map(|p| [p[0], p[1], p[2]]).collect());
+    /// let result: EncoderResult<u8> = encoder.pipeline(2, 2)?.queue_depth(4).encode(frames)?;
+    /// # Ok::<(), jpegxl_rs::EncodeError>(())
+    /// ```
+    pub fn encode<T, I>(self, frames: I) -> Result<EncoderResult<U>, EncodeError>
+    where
+        T: PixelType + Send,
+        I: IntoIterator<Item = Vec<T>>,
+        I::IntoIter: Send,
+    {
+        let Self {
+            encoder,
+            queue_depth,
+            format,
+            mut on_frame,
+            ..
+        } = self;
+        let frames = frames.into_iter();
+        let mut buffer = Vec::with_capacity(encoder.init_buffer_size);
+
+        let added = thread::scope(|s| {
+            let (tx, rx) = mpsc::sync_channel(queue_depth);
+            s.spawn(move || {
+                for frame in frames {
+                    // The receiver is gone after an error
+                    if tx.send(frame).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            // The last frame has to be closed before it is compressed, so each frame is
+            // compressed once the next one arrives
+            let mut rx = rx.into_iter().peekable();
+            let mut added = 0;
+            while let Some(data) = rx.next() {
+                encoder.add_frame(&EncoderFrame::new(&data).pixel_format(format))?;
+                added += 1;
+                if rx.peek().is_none() {
+                    unsafe { JxlEncoderCloseInput(encoder.enc) };
+                }
+                drop(data);
+                encoder.write_output(&mut buffer)?;
+                if let Some(f) = &mut on_frame {
+                    f(added);
+                }
+            }
+            if added == 0 {
+                unsafe { JxlEncoderCloseInput(encoder.enc) };
+                encoder.write_output(&mut buffer)?;
+            }
+            Ok(())
+        });
+        encoder.reset();
+
+        added.map(|()| EncoderResult {
+            data: buffer,
+            _pixel_type: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use crate::{decoder_builder, encode::EncoderFrame, encoder_builder};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_pipeline() -> TestResult {
+        let frames: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i * 40; 3 * 8 * 8]).collect();
+        let mut encoder = encoder_builder().build()?;
+
+        let mut multiple = encoder.multiple::<u8>(8, 8)?;
+        for frame in &frames {
+            multiple = multiple.add_frame(&EncoderFrame::new(frame))?;
+        }
+        let expected = multiple.encode()?;
+
+        for queue_depth in [0, 1, 4] {
+            let result = encoder
+                .pipeline::<u8>(8, 8)?
+                .queue_depth(queue_depth)
+                .encode(frames.iter().cloned())?;
+            assert_eq!(result.data, expected.data);
+        }
+        decoder_builder().build()?.decode(&expected)?;
+
+        // Frames of the wrong size stop the pipeline
+        let frames = [vec![0u8; 3 * 8 * 8], vec![0u8; 2]];
+        assert!(encoder
+            .pipeline::<u8>(8, 8)?
+            .encode(frames.clone().into_iter().cycle())
+            .is_err());
+        // The encoder is left ready for the next image
+        let [frame, _] = frames;
+        decoder_builder()
+            .build()?
+            .decode(&encoder.pipeline::<u8>(8, 8)?.encode([frame])?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_pipeline_overlap() -> TestResult {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&produced);
+        let frames = (0..8u8).map(move |i| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            vec![i * 30; 3 * 8 * 8]
+        });
+
+        let mut progress = vec![];
+        let mut encoder = encoder_builder().build()?;
+        let result = encoder
+            .pipeline::<u8>(8, 8)?
+            .queue_depth(1)
+            .on_frame(|compressed| progress.push((compressed, produced.load(Ordering::SeqCst))))
+            .encode(frames)?;
+        decoder_builder().build()?.decode(&result)?;
+
+        assert_eq!(progress.len(), 8);
+        for (compressed, produced) in progress {
+            // Frames are compressed while later ones are prepared: the producer stays ahead by
+            // at most the frame being added, the queued one and the one being sent
+            assert!(produced >= compressed);
+            assert!(produced <= compressed + 3, "{produced} > {compressed} + 3");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::ops::Deref;
+
+use jpegxl_sys::encode::FrameSetting;
+
+use crate::{common::PixelType, memory::MemoryManager, parallel::JxlParallelRunner, EncodeError};
+
+use super::{
+    EncoderFrame, EncoderResult, JxlEncoder, JxlEncoderBuilder, Metadata, MultiFrames, Pipeline,
+};
+
+impl<'prl, 'mm> JxlEncoderBuilder<'prl, 'mm> {
+    /// Build a [`SendEncoder`], which can be moved to another thread
+    ///
+    /// The parallel runner and memory manager are given here instead, replacing any set on
+    /// the builder, as the encoder uses them from whichever thread it is moved to.
+    ///
+    /// # Errors
+    /// Return [`EncodeError::CannotCreateEncoder`] if it fails to create the encoder
+    pub fn build_send(
+        &self,
+        parallel_runner: Option<&'prl (dyn JxlParallelRunner + Sync)>,
+        memory_manager: Option<&'mm (dyn MemoryManager + Sync)>,
+    ) -> Result<SendEncoder<'prl, 'mm>, EncodeError> {
+        let mut builder = self.clone();
+        builder.parallel_runner = Some(parallel_runner.map(|pr| pr as &dyn JxlParallelRunner));
+        builder.memory_manager = Some(memory_manager.map(|mm| mm as &dyn MemoryManager));
+        builder.build().map(SendEncoder)
+    }
+}
+
+/// [`JxlEncoder`] which is [`Send`], built with [`JxlEncoderBuilder::build_send`]
+///
+/// Like [`SendDecoder`](crate::decode::SendDecoder), the encoder is never lent out mutably,
+/// so its [`Sync`] parallel runner and memory manager cannot be replaced. Options can be
+/// read through [`Deref`] and are the ones given to the builder, the encoding methods are
+/// forwarded.
+pub struct SendEncoder<'prl, 'mm>(pub(crate) JxlEncoder<'prl, 'mm>);
+
+// SAFETY: a libjxl encoder may be used from any thread, one at a time, which `&mut self`
+// and the lack of `Sync` ensure. The parallel runner and memory manager it refers to are
+// `Sync`, and everything else it holds is `Send`.
+unsafe impl Send for SendEncoder<'_, '_> {}
+
+impl<'prl, 'mm> Deref for SendEncoder<'prl, 'mm> {
+    type Target = JxlEncoder<'prl, 'mm>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'prl, 'mm> SendEncoder<'prl, 'mm> {
+    /// Set a specific encoder frame setting, see [`JxlEncoder::set_frame_option`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if it fails to set frame option
+    pub fn set_frame_option(
+        &mut self,
+        option: FrameSetting,
+        value: i64,
+    ) -> Result<(), EncodeError> {
+        self.0.set_frame_option(option, value)
+    }
+
+    /// Return a wrapper type for adding multiple frames, see [`JxlEncoder::multiple`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if it fails to set up the encoder
+    pub fn multiple<'enc, U: PixelType>(
+        &'enc mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<MultiFrames<'enc, 'prl, 'mm, U>, EncodeError> {
+        self.0.multiple(width, height)
+    }
+
+    /// Return a [`Pipeline`] to add multiple frames, see [`JxlEncoder::pipeline`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if it fails to set up the encoder
+    pub fn pipeline<'enc, U: PixelType>(
+        &'enc mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<Pipeline<'enc, 'prl, 'mm, U>, EncodeError> {
+        self.0.pipeline(width, height)
+    }
+
+    /// Add a metadata box to the encoder, see [`JxlEncoder::add_metadata`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if it fails to add metadata
+    pub fn add_metadata(&mut self, metadata: &Metadata, compress: bool) -> Result<(), EncodeError> {
+        self.0.add_metadata(metadata, compress)
+    }
+
+    /// Encode a JPEG XL image from existing raw JPEG data, see [`JxlEncoder::encode_jpeg`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if the internal encoder fails to encode
+    pub fn encode_jpeg(&mut self, data: &[u8]) -> Result<EncoderResult<u8>, EncodeError> {
+        self.0.encode_jpeg(data)
+    }
+
+    /// Encode a JPEG XL image from pixels, see [`JxlEncoder::encode`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if the internal encoder fails to encode
+    pub fn encode<T: PixelType, U: PixelType>(
+        &mut self,
+        data: &[T],
+        width: u32,
+        height: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        self.0.encode(data, width, height)
+    }
+
+    /// Encode a JPEG XL image from a frame, see [`JxlEncoder::encode_frame`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError`] if the internal encoder fails to encode
+    pub fn encode_frame<T: PixelType, U: PixelType>(
+        &mut self,
+        frame: &EncoderFrame<T>,
+        width: u32,
+        height: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        self.0.encode_frame(frame, width, height)
+    }
+
+    /// Encode a JPEG XL image from a frame of integer samples using only the lower
+    /// `bits_per_sample` bits, see [`JxlEncoder::encode_frame_with_bit_depth`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError::NotSupported`] if `bits_per_sample` does not fit `T` or `U`,
+    /// or another [`EncodeError`] if the internal encoder fails to encode
+    pub fn encode_frame_with_bit_depth<T: PixelType, U: PixelType>(
+        &mut self,
+        frame: &EncoderFrame<T>,
+        width: u32,
+        height: u32,
+        bits_per_sample: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        self.0
+            .encode_frame_with_bit_depth(frame, width, height, bits_per_sample)
+    }
+
+    /// Encode a JPEG file losslessly, see [`JxlEncoder::encode_jpeg_file`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError::Io`] when the file cannot be mapped, or an [`EncodeError`]
+    /// when internal encoder fails
+    #[cfg(feature = "memmap2")]
+    pub fn encode_jpeg_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<EncoderResult<u8>, EncodeError> {
+        self.0.encode_jpeg_file(path)
+    }
+
+    /// Encode a file of raw interleaved pixels, see [`JxlEncoder::encode_file`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError::Io`] when the file cannot be mapped, or an [`EncodeError`]
+    /// when internal encoder fails
+    #[cfg(feature = "memmap2")]
+    pub fn encode_file<T: PixelType, U: PixelType>(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        width: u32,
+        height: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        self.0.encode_file::<T, U>(path, width, height)
+    }
+
+    /// Unwrap the inner [`JxlEncoder`], which is not [`Send`]
+    #[must_use]
+    pub fn into_inner(self) -> JxlEncoder<'prl, 'mm> {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use testresult::TestResult;
+
+    use crate::{
+        decoder_builder, encode::EncoderResult, encoder_builder, memory::RustAllocator,
+        parallel::shared_runner::SharedRunner,
+    };
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_send_encoder() -> TestResult {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let runner = SharedRunner::default();
+        let mut encoder = encoder_builder()
+            .lossless(true)
+            .uses_original_profile(true)
+            .build_send(Some(&runner), Some(&RustAllocator))?;
+        assert_send(&encoder);
+        assert!(encoder.parallel_runner.is_some() && encoder.memory_manager.is_some());
+
+        let pixels: Vec<u8> = (0..8 * 8 * 3u8).map(|i| i.wrapping_mul(7)).collect();
+        let result: EncoderResult<u8> = thread::scope(|s| {
+            s.spawn(|| {
+                assert!(encoder.lossless);
+                encoder.encode(&pixels, 8, 8)
+            })
+            .join()
+            .expect("Encoding thread panicked")
+        })?;
+        let encoder = encoder.into_inner();
+        assert!(encoder.memory_manager.is_some());
+
+        let (_, decoded) = decoder_builder().build()?.decode_with::<u8>(&result)?;
+        assert_eq!(decoded, pixels);
+
+        Ok(())
+    }
+}
@@ -36,6 +36,21 @@ pub enum DecodeError {
     /// Unsupported Pixel bit width
     #[error("Unsupported Pixel bit width: {0}")]
     UnsupportedBitWidth(u32),
+    /// The image has no data to reconstruct a JPEG file
+    #[error("The image was not recompressed from a JPEG file")]
+    NoJpegReconstruction,
+    /// Output surface cannot hold the image
+    #[error("The output surface is too small for the image")]
+    SurfaceTooSmall,
+    /// Reading the input or writing the output failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Cancelled by the decoder's cancellation token
+    #[error("Decoding was cancelled")]
+    Cancelled,
+    /// The image out callback panicked while writing pixels
+    #[error("The image out callback panicked")]
+    CallbackPanicked,
     /// Unknown status
     #[error("Unknown status: `{0:?}`")]
     UnknownStatus(JxlDecoderStatus),
@@ -69,11 +84,28 @@ pub enum EncodeError {
     /// The encoder API is used in an incorrect way. In this case, a debug build of libjxl should output a specific error message
     #[error("The encoder API is used in an incorrect way")]
     ApiUsage,
+    /// Reading the input failed
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Cancelled by the encoder's cancellation token
+    #[error("Encoding was cancelled")]
+    Cancelled,
     /// Unknown status
     #[error("Unknown status: `{0:?}`")]
     UnknownStatus(JxlEncoderError),
 }
 
+/// Any error of this crate, for callers both decoding and encoding
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Decoding failed
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    /// Encoding failed
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+}
+
 /// Error mapping from underlying C const to [`DecodeError`] enum
 pub(crate) fn check_dec_status(status: JxlDecoderStatus) -> Result<(), DecodeError> {
     match status {
@@ -140,4 +172,24 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn unified_error() {
+        fn transcode(data: &[u8]) -> Result<Vec<u8>, Error> {
+            let decoder = crate::decoder_builder().build()?;
+            let (metadata, pixels) = decoder.decode_with::<u8>(data)?;
+            let mut encoder = crate::encoder_builder().has_alpha(true).build()?;
+            let frame = crate::encode::EncoderFrame::new(&pixels).num_channels(4);
+            Ok(encoder
+                .encode_frame::<u8, u8>(&frame, metadata.width, metadata.height)?
+                .data)
+        }
+
+        assert!(transcode(crate::tests::SAMPLE_JXL).is_ok());
+        assert!(matches!(
+            transcode(&[]),
+            Err(Error::Decode(DecodeError::InvalidInput))
+        ));
+    }
 }
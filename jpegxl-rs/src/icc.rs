@@ -0,0 +1,330 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Summary of ICC profiles, for showing what an image's profile describes
+
+use byteorder::{ByteOrder, BE};
+
+/// Color space of the data described by an ICC profile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IccColorSpace {
+    /// RGB
+    Rgb,
+    /// Grayscale
+    Gray,
+    /// CMYK
+    Cmyk,
+    /// Any other color space, by its signature
+    Other([u8; 4]),
+}
+
+/// Kind of tone curve of an ICC profile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferCurve {
+    /// Linear, gamma 1
+    Linear,
+    /// Pure power function with the given gamma
+    Gamma(f64),
+    /// Parametric curve of the given ICC function type 1 to 4, e.g. sRGB
+    Parametric(u16),
+    /// Sampled curve with the given number of entries, e.g. PQ or HLG
+    Table(u32),
+}
+
+/// Summary of the main properties of an ICC profile
+///
+/// # Example
+/// ```
+/// # use jpegxl_rs::{decoder_builder, icc::IccSummary};
+/// # let sample = std::fs::read("../samples/sample.jxl").unwrap();
+/// let decoder = decoder_builder().icc_profile(true).build().unwrap();
+/// let icc = decoder.decode(&sample).unwrap().0.icc_profile.unwrap();
+/// let summary = IccSummary::parse(&icc).unwrap();
+/// println!("{}", summary.description.unwrap_or_default());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct IccSummary {
+    /// Major and minor version of the profile
+    pub version: (u8, u8),
+    /// Color space of the data
+    pub color_space: IccColorSpace,
+    /// Description of the profile, in English if it has several languages
+    pub description: Option<String>,
+    /// Chromaticity of the media white point
+    pub white_point: Option<[f64; 2]>,
+    /// Chromaticities of the red, green and blue primaries. Profiles store them adapted to
+    /// the D50 white point, which is undone with their chromatic adaptation, or Bradford
+    /// to the media white point for profiles without one
+    pub primaries: Option<[[f64; 2]; 3]>,
+    /// Tone curve of the first channel, `None` for profiles built on lookup tables
+    pub transfer: Option<TransferCurve>,
+}
+
+const HEADER_SIZE: usize = 128;
+
+impl IccSummary {
+    /// Parse the header and tags of an ICC profile.
+    /// Return `None` if it is not a valid profile
+    #[must_use]
+    pub fn parse(icc: &[u8]) -> Option<Self> {
+        let header = icc.get(..HEADER_SIZE + 4)?;
+        if &header[36..40] != b"acsp" {
+            return None;
+        }
+
+        let color_space = match <[u8; 4]>::try_from(&header[16..20]).ok()? {
+            [b'R', b'G', b'B', b' '] => IccColorSpace::Rgb,
+            [b'G', b'R', b'A', b'Y'] => IccColorSpace::Gray,
+            [b'C', b'M', b'Y', b'K'] => IccColorSpace::Cmyk,
+            other => IccColorSpace::Other(other),
+        };
+        let profile = Profile { icc };
+
+        let white_point = profile.tag(*b"wtpt").and_then(xyz);
+        let adaptation = profile
+            .tag(*b"chad")
+            .and_then(matrix)
+            .or_else(|| white_point.and_then(bradford))
+            .and_then(inverse);
+        let primaries = [*b"rXYZ", *b"gXYZ", *b"bXYZ"].map(|sig| {
+            let xyz = profile.tag(sig).and_then(xyz)?;
+            xy(adaptation.map_or(xyz, |m| multiply(&m, xyz)))
+        });
+        let trc = if color_space == IccColorSpace::Gray {
+            *b"kTRC"
+        } else {
+            *b"rTRC"
+        };
+
+        Some(Self {
+            version: (header[8], header[9] >> 4),
+            color_space,
+            description: profile.tag(*b"desc").and_then(description),
+            white_point: white_point.and_then(xy),
+            primaries: match primaries {
+                [Some(r), Some(g), Some(b)] => Some([r, g, b]),
+                _ => None,
+            },
+            transfer: profile.tag(trc).and_then(transfer),
+        })
+    }
+}
+
+struct Profile<'a> {
+    icc: &'a [u8],
+}
+
+impl<'a> Profile<'a> {
+    /// Find the data of a tag in the tag table
+    fn tag(&self, sig: [u8; 4]) -> Option<&'a [u8]> {
+        let count = BE::read_u32(self.icc.get(HEADER_SIZE..HEADER_SIZE + 4)?) as usize;
+        let table = self.icc.get(HEADER_SIZE + 4..)?;
+        table
+            .chunks_exact(12)
+            .take(count)
+            .find(|entry| entry[..4] == sig)
+            .and_then(|entry| {
+                let offset = BE::read_u32(&entry[4..8]) as usize;
+                let size = BE::read_u32(&entry[8..12]) as usize;
+                self.icc.get(offset..offset.checked_add(size)?)
+            })
+    }
+}
+
+fn s15_fixed16(data: &[u8]) -> f64 {
+    f64::from(BE::read_i32(data)) / 65536.0
+}
+
+type Matrix = [[f64; 3]; 3];
+
+/// D50 illuminant of the profile connection space
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// Values of an `XYZ ` tag
+fn xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(..4)? != b"XYZ " {
+        return None;
+    }
+    let xyz = tag.get(8..20)?;
+    Some([0, 4, 8].map(|i| s15_fixed16(&xyz[i..])))
+}
+
+/// Chromaticity of XYZ values
+fn xy([x, y, z]: [f64; 3]) -> Option<[f64; 2]> {
+    let sum = x + y + z;
+    (sum > 0.0).then(|| [x / sum, y / sum])
+}
+
+/// Matrix of an `sf32` tag, like `chad`
+fn matrix(tag: &[u8]) -> Option<Matrix> {
+    if tag.get(..4)? != b"sf32" {
+        return None;
+    }
+    let values = tag.get(8..44)?;
+    Some([0, 1, 2].map(|row| [0, 1, 2].map(|col| s15_fixed16(&values[(row * 3 + col) * 4..]))))
+}
+
+/// Bradford adaptation from `white` to D50
+fn bradford(white: [f64; 3]) -> Option<Matrix> {
+    const BRADFORD: Matrix = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+
+    // Scale the cone responses to `white` to the ones to D50
+    let [source, destination] = [white, D50].map(|w| multiply(&BRADFORD, w));
+    let scaled = [0, 1, 2].map(|i| BRADFORD[i].map(|v| v * destination[i] / source[i]));
+    let back = inverse(BRADFORD)?;
+    Some(
+        [0, 1, 2]
+            .map(|row| [0, 1, 2].map(|col| (0..3).map(|k| back[row][k] * scaled[k][col]).sum())),
+    )
+}
+
+fn multiply(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn inverse(m: Matrix) -> Option<Matrix> {
+    let cofactor = |r: usize, c: usize| {
+        let [r1, r2] = [(r + 1) % 3, (r + 2) % 3];
+        let [c1, c2] = [(c + 1) % 3, (c + 2) % 3];
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let det: f64 = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum();
+    (det.abs() > f64::EPSILON)
+        .then(|| [0, 1, 2].map(|row| [0, 1, 2].map(|col| cofactor(col, row) / det)))
+}
+
+/// Text of a `desc` or `mluc` tag
+fn description(tag: &[u8]) -> Option<String> {
+    match tag.get(..4)? {
+        b"desc" => {
+            let len = BE::read_u32(tag.get(8..12)?) as usize;
+            let text = tag.get(12..12 + len)?;
+            let text = text.split(|&c| c == 0).next().unwrap_or_default();
+            Some(String::from_utf8_lossy(text).into_owned())
+        }
+        b"mluc" => {
+            let count = BE::read_u32(tag.get(8..12)?) as usize;
+            let records = tag.get(16..)?.chunks_exact(12).take(count);
+            let mut records = records.clone().filter(|r| &r[..2] == b"en").chain(records);
+            let record = records.next()?;
+            let len = BE::read_u32(&record[4..8]) as usize;
+            let offset = BE::read_u32(&record[8..12]) as usize;
+            let text = tag.get(offset..offset.checked_add(len)?)?;
+            let units: Vec<u16> = text.chunks_exact(2).map(BE::read_u16).collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+/// Kind of curve of a `curv` or `para` tag
+fn transfer(tag: &[u8]) -> Option<TransferCurve> {
+    match tag.get(..4)? {
+        b"curv" => match BE::read_u32(tag.get(8..12)?) {
+            0 => Some(TransferCurve::Linear),
+            1 => {
+                let gamma = f64::from(BE::read_u16(tag.get(12..14)?)) / 256.0;
+                Some(TransferCurve::Gamma(gamma))
+            }
+            n => Some(TransferCurve::Table(n)),
+        },
+        b"para" => match BE::read_u16(tag.get(8..10)?) {
+            0 => {
+                let gamma = tag.get(12..16)?;
+                // Exactly 1.0 in s15Fixed16
+                Some(if BE::read_i32(gamma) == 1 << 16 {
+                    TransferCurve::Linear
+                } else {
+                    TransferCurve::Gamma(s15_fixed16(gamma))
+                })
+            }
+            function_type => Some(TransferCurve::Parametric(function_type)),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{decoder_builder, encode::ColorEncoding, encoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_parse() -> TestResult {
+        let decoder = decoder_builder().icc_profile(true).build()?;
+        let icc = decoder.decode(SAMPLE_JXL)?.0.icc_profile;
+        let summary =
+            IccSummary::parse(&icc.expect("ICC profile not retrieved")).expect("Failed to parse");
+
+        assert_eq!(summary.color_space, IccColorSpace::Rgb);
+        assert_eq!(summary.version.0, 4);
+        assert!(summary.description.as_ref().is_some_and(|d| !d.is_empty()));
+        let [x, y] = summary.white_point.expect("No white point");
+        assert!((x - 0.3457).abs() < 1e-3 && (y - 0.3585).abs() < 1e-3);
+        assert_primaries(&summary, [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]]);
+        assert!(summary.transfer.is_some());
+
+        assert_eq!(IccSummary::parse(&[0; 64]), None);
+        assert_eq!(IccSummary::parse(&[0; 256]), None);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_wide_gamut() -> TestResult {
+        let data = encoder_builder()
+            .color_encoding(ColorEncoding::DisplayP3)
+            .uses_original_profile(true)
+            .build()?
+            .encode::<u8, u8>(&[0; 3 * 4], 2, 2)?;
+        let decoder = decoder_builder().icc_profile(true).build()?;
+        let icc = decoder.decode(&data)?.0.icc_profile;
+        let summary =
+            IccSummary::parse(&icc.expect("ICC profile not retrieved")).expect("Failed to parse");
+
+        // Not the D50 adapted colorants
+        assert_primaries(&summary, [[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]]);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_bradford() {
+        let d65 = [0.9505, 1.0, 1.089];
+        let adapted = multiply(&bradford(d65).expect("Singular matrix"), d65);
+        for (v, d50) in adapted.into_iter().zip(D50) {
+            assert!((v - d50).abs() < 1e-4, "{adapted:?}");
+        }
+    }
+
+    fn assert_primaries(summary: &IccSummary, expected: [[f64; 2]; 3]) {
+        let primaries = summary.primaries.expect("No primaries");
+        for (p, e) in primaries.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((p - e).abs() < 2e-3, "{primaries:?} != {expected:?}");
+        }
+    }
+}
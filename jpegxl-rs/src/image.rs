@@ -19,12 +19,13 @@
 
 use std::mem::MaybeUninit;
 
+use half::f16;
 use image::{DynamicImage, ImageBuffer};
 use jpegxl_sys::types::{JxlDataType, JxlPixelFormat};
 
 use crate::{
     common::PixelType,
-    decode::{JxlDecoder, Metadata},
+    decode::{ImageOut, Input, JxlDecoder, Metadata},
     DecodeError,
 };
 
@@ -32,6 +33,10 @@ use crate::{
 pub trait ToDynamic {
     /// Decode the JPEG XL image to a [`DynamicImage`]
     ///
+    /// The pixel type is picked from the image header. Since [`DynamicImage`] has no `f16` or
+    /// grayscale `f32` variants, those images are widened to [`DynamicImage::ImageRgb32F`] or
+    /// [`DynamicImage::ImageRgba32F`].
+    ///
     /// # Errors
     /// Return a [`DecodeError`] when internal decoding fails.
     /// Return `Ok(None)` when the image is not representable as a [`DynamicImage`]
@@ -48,20 +53,23 @@ pub trait ToDynamic {
     ) -> Result<Option<DynamicImage>, DecodeError>;
 }
 
-impl<'pr, 'mm> ToDynamic for JxlDecoder<'pr, 'mm> {
+impl ToDynamic for JxlDecoder<'_, '_> {
     fn decode_to_image(&self, data: &[u8]) -> Result<Option<DynamicImage>, DecodeError> {
         let mut buffer = vec![];
         let mut pixel_format = MaybeUninit::uninit();
         let metadata = self.decode_internal(
-            data,
+            Input::Slice(data),
             None,
             false,
             None,
             pixel_format.as_mut_ptr(),
-            &mut buffer,
+            ImageOut::Buffer(&mut buffer),
         )?;
 
         let pixel_format = unsafe { pixel_format.assume_init() };
+        if let JxlDataType::Float | JxlDataType::Float16 = pixel_format.data_type {
+            return Ok(to_rgb32f(metadata, &pixel_format, &buffer));
+        }
         Ok(to_image(metadata, &pixel_format, buffer))
     }
 
@@ -72,12 +80,12 @@ impl<'pr, 'mm> ToDynamic for JxlDecoder<'pr, 'mm> {
         let mut buffer = vec![];
         let mut pixel_format = MaybeUninit::uninit();
         let metadata = self.decode_internal(
-            data,
+            Input::Slice(data),
             Some(T::pixel_type()),
             false,
             None,
             pixel_format.as_mut_ptr(),
-            &mut buffer,
+            ImageOut::Buffer(&mut buffer),
         )?;
 
         let pixel_format = unsafe { pixel_format.assume_init() };
@@ -131,17 +139,49 @@ fn to_image(
     }
 }
 
+fn to_rgb32f(
+    Metadata { width, height, .. }: Metadata,
+    pixel_format: &JxlPixelFormat,
+    buffer: &[u8],
+) -> Option<DynamicImage> {
+    let data = if pixel_format.data_type == JxlDataType::Float16 {
+        f16::convert(buffer, pixel_format)
+            .into_iter()
+            .map(f32::from)
+            .collect()
+    } else {
+        f32::convert(buffer, pixel_format)
+    };
+
+    match pixel_format.num_channels {
+        1 => ImageBuffer::from_raw(width, height, data.iter().flat_map(|&v| [v; 3]).collect())
+            .map(DynamicImage::ImageRgb32F),
+        2 => ImageBuffer::from_raw(
+            width,
+            height,
+            data.chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+        )
+        .map(DynamicImage::ImageRgba32F),
+        3 => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgb32F),
+        4 => ImageBuffer::from_raw(width, height, data).map(DynamicImage::ImageRgba32F),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         decode::PixelFormat,
         decoder_builder,
+        encode::{ColorEncoding, EncoderFrame, EncoderResult},
+        encoder_builder,
         tests::{SAMPLE_JXL, SAMPLE_JXL_GRAY, SAMPLE_PNG},
         ThreadsRunner,
     };
 
-    use half::f16;
     use pretty_assertions::assert_eq;
     use testresult::TestResult;
 
@@ -171,6 +211,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn widen_float() -> TestResult {
+        let sample = image::load_from_memory_with_format(SAMPLE_PNG, image::ImageFormat::Png)?;
+        let decoder = decoder_builder().build()?;
+
+        let rgb = sample.to_rgb8();
+        let mut encoder = encoder_builder().build()?;
+        let res: EncoderResult<f16> = encoder.encode(rgb.as_raw(), rgb.width(), rgb.height())?;
+        let img = decoder
+            .decode_to_image(&res)?
+            .expect("Failed to create DynamicImage");
+        assert!(matches!(img, DynamicImage::ImageRgb32F(_)));
+
+        let luma = sample.to_luma8();
+        let mut encoder = encoder_builder()
+            .color_encoding(ColorEncoding::SrgbLuma)
+            .build()?;
+        let res: EncoderResult<f32> = encoder.encode_frame(
+            &EncoderFrame::new(luma.as_raw()).num_channels(1),
+            luma.width(),
+            luma.height(),
+        )?;
+        let img = decoder
+            .decode_to_image(&res)?
+            .expect("Failed to create DynamicImage");
+        assert!(matches!(img, DynamicImage::ImageRgb32F(_)));
+        assert_eq!((img.width(), img.height()), (luma.width(), luma.height()));
+
+        Ok(())
+    }
+
     #[test]
     #[cfg(feature = "threads")]
     #[cfg_attr(coverage_nightly, coverage(off))]
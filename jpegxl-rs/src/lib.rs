@@ -22,26 +22,53 @@ along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 #[macro_use]
 extern crate derive_builder;
 
+pub mod cms;
 mod common;
 pub mod decode;
 pub mod encode;
 mod errors;
+pub mod icc;
 pub mod memory;
 pub mod parallel;
+pub mod pool;
+mod transcode;
+pub mod unwind;
 pub mod utils;
 
 #[cfg(feature = "image")]
 pub mod image;
 
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+
+#[cfg(feature = "memmap2")]
+mod mmap;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+
 #[cfg(test)]
 mod tests;
 
-pub use common::Endianness;
+pub use common::{
+    normalize_u16, quantize_f32, CancellationToken, DataType, Dithering, Endianness, PixelFormat,
+};
 pub use decode::decoder_builder;
 pub use encode::encoder_builder;
-pub use errors::{DecodeError, EncodeError};
+pub use errors::{DecodeError, EncodeError, Error};
+pub use transcode::{reconstruct_jpeg, reencode, transcode_jpeg_to_jxl};
+
+#[cfg(feature = "memmap2")]
+pub use mmap::FileFormat;
 
+#[cfg(feature = "rayon")]
+pub use parallel::rayon_runner::RayonRunner;
 #[cfg(feature = "threads")]
 pub use parallel::resizable_runner::ResizableRunner;
 #[cfg(feature = "threads")]
+pub use parallel::shared_runner::SharedRunner;
+#[cfg(feature = "threads")]
 pub use parallel::threads_runner::ThreadsRunner;
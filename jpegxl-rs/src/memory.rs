@@ -17,7 +17,12 @@
 
 //! Memory manager interface
 
-use std::ffi::c_void;
+use std::{
+    alloc::{alloc, dealloc, Layout},
+    ffi::c_void,
+    ptr::null_mut,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use jpegxl_sys::memory_manager::JxlMemoryManager;
 
@@ -27,7 +32,6 @@ pub type AllocFn = unsafe extern "C-unwind" fn(opaque: *mut c_void, size: usize)
 pub type FreeFn = unsafe extern "C-unwind" fn(opaque: *mut c_void, address: *mut c_void);
 
 /// General trait for a memory manager
-
 #[allow(clippy::module_name_repetitions)]
 pub trait MemoryManager {
     /// Return a custom allocating function
@@ -46,13 +50,149 @@ pub trait MemoryManager {
     }
 }
 
+/// Memory manager backed by the Rust global allocator
+///
+/// Useful on WebAssembly, to keep the allocations of libjxl in the allocator of the module.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RustAllocator;
+
+impl RustAllocator {
+    /// Room in front of every allocation to store its size, keeping the alignment of `malloc`
+    const HEADER: usize = 16;
+
+    fn layout(size: usize) -> Option<Layout> {
+        Layout::from_size_align(size.checked_add(Self::HEADER)?, Self::HEADER).ok()
+    }
+}
+
+impl RustAllocator {
+    // Allocations are aligned to the header size
+    #[allow(clippy::cast_ptr_alignment)]
+    unsafe fn allocate(size: usize) -> *mut c_void {
+        let Some(layout) = Self::layout(size) else {
+            return null_mut();
+        };
+        let ptr = alloc(layout);
+        if ptr.is_null() {
+            return null_mut();
+        }
+        ptr.cast::<usize>().write(size);
+        ptr.add(Self::HEADER).cast()
+    }
+
+    /// Free a non-null `address`, returning the size requested for it
+    #[allow(clippy::cast_ptr_alignment)]
+    unsafe fn deallocate(address: *mut c_void) -> usize {
+        let ptr = address.cast::<u8>().sub(Self::HEADER);
+        let size = ptr.cast::<usize>().read();
+        // The layout was valid when allocating
+        dealloc(ptr, Self::layout(size).unwrap_unchecked());
+        size
+    }
+}
+
+impl MemoryManager for RustAllocator {
+    fn alloc(&self) -> AllocFn {
+        unsafe extern "C-unwind" fn alloc_fn(_opaque: *mut c_void, size: usize) -> *mut c_void {
+            RustAllocator::allocate(size)
+        }
+
+        alloc_fn
+    }
+
+    fn free(&self) -> FreeFn {
+        unsafe extern "C-unwind" fn free_fn(_opaque: *mut c_void, address: *mut c_void) {
+            if !address.is_null() {
+                RustAllocator::deallocate(address);
+            }
+        }
+
+        free_fn
+    }
+}
+
+/// Allocation totals recorded by [`TrackingAllocator`], in bytes requested by libjxl
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes currently allocated
+    pub current: usize,
+    /// Highest amount of bytes allocated at once
+    pub peak: usize,
+    /// Number of allocations made
+    pub allocations: usize,
+}
+
+/// Memory manager backed by the Rust global allocator, recording how much libjxl allocates
+///
+/// Use one per encoder or decoder, or call [`reset_peak`](Self::reset_peak) between
+/// operations, to get the totals of each encoding or decoding.
+///
+/// # Example
+/// ```
+/// # use jpegxl_rs::{decoder_builder, memory::TrackingAllocator};
+/// # let sample = std::fs::read("../samples/sample.jxl").unwrap();
+/// let mm = TrackingAllocator::default();
+/// let decoder = decoder_builder().memory_manager(&mm).build().unwrap();
+/// decoder.decode(&sample).unwrap();
+/// println!("Peak usage: {} bytes", mm.stats().peak);
+/// ```
+#[derive(Debug, Default)]
+pub struct TrackingAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+}
+
+impl TrackingAllocator {
+    /// Return the totals recorded so far
+    #[must_use]
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            current: self.current.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Restart recording the peak and allocation count from the current usage
+    pub fn reset_peak(&self) {
+        self.peak
+            .store(self.current.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.allocations.store(0, Ordering::Relaxed);
+    }
+}
+
+impl MemoryManager for TrackingAllocator {
+    fn alloc(&self) -> AllocFn {
+        unsafe extern "C-unwind" fn alloc_fn(opaque: *mut c_void, size: usize) -> *mut c_void {
+            let ptr = RustAllocator::allocate(size);
+            if !ptr.is_null() {
+                let mm = &*opaque.cast::<TrackingAllocator>();
+                let current = mm.current.fetch_add(size, Ordering::Relaxed) + size;
+                mm.peak.fetch_max(current, Ordering::Relaxed);
+                mm.allocations.fetch_add(1, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        alloc_fn
+    }
+
+    fn free(&self) -> FreeFn {
+        unsafe extern "C-unwind" fn free_fn(opaque: *mut c_void, address: *mut c_void) {
+            if !address.is_null() {
+                let size = RustAllocator::deallocate(address);
+                let mm = &*opaque.cast::<TrackingAllocator>();
+                mm.current.fetch_sub(size, Ordering::Relaxed);
+            }
+        }
+
+        free_fn
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::{
-        ptr::null_mut,
-        sync::atomic::{AtomicUsize, Ordering},
-    };
-
     use crate::{decoder_builder, encoder_builder};
 
     use super::*;
@@ -166,6 +306,38 @@ pub(crate) mod tests {
         assert!(encoder_builder().memory_manager(&mm).build().is_ok());
     }
 
+    #[test]
+    fn test_rust_allocator() -> testresult::TestResult {
+        let mm = RustAllocator;
+        let decoder = decoder_builder().memory_manager(&mm).build()?;
+        decoder.decode(crate::tests::SAMPLE_JXL)?;
+
+        let mut encoder = encoder_builder().memory_manager(&mm).build()?;
+        let _: crate::encode::EncoderResult<u8> = encoder.encode(&[0u8; 3 * 4], 2, 2)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracking_allocator() -> testresult::TestResult {
+        let mm = TrackingAllocator::default();
+        let decoder = decoder_builder().memory_manager(&mm).build()?;
+        decoder.decode(crate::tests::SAMPLE_JXL)?;
+        let stats = mm.stats();
+        assert!(stats.allocations > 0);
+        assert!(stats.peak >= stats.current);
+        drop(decoder);
+        assert_eq!(mm.stats().current, 0);
+
+        mm.reset_peak();
+        assert_eq!(mm.stats(), MemoryStats::default());
+        let mut encoder = encoder_builder().memory_manager(&mm).build()?;
+        let _: crate::encode::EncoderResult<u8> = encoder.encode(&[0u8; 3 * 4], 2, 2)?;
+        assert!(mm.stats().peak > 0);
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic = "Stack unwind test"]
     fn test_unwind() {
@@ -0,0 +1,269 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Memory-mapped file input and output, with the `memmap2` feature
+//!
+//! The files are handed to libjxl without being copied into memory first. They must not
+//! be modified by other processes while they are mapped.
+
+use std::{fs::File, path::Path};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::{
+    common::PixelType,
+    decode::{JxlDecoder, Metadata, PixelFormat, Pixels},
+    encode::{EncoderResult, JxlEncoder},
+    DecodeError, EncodeError, Endianness,
+};
+
+/// Layout of the file written by [`JxlDecoder::decode_to_file`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Interleaved samples only, in the endianness of the decoder's `pixel_format`
+    #[default]
+    Raw,
+    /// Netpbm image: PGM or PPM for gray or RGB images, PAM with an alpha channel.
+    /// Only for integer samples, stored in big endian as the format requires
+    Pnm,
+}
+
+fn map(path: &Path) -> std::io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: Documented at the module level, the file is only read while it is mapped
+    unsafe { Mmap::map(&file) }
+}
+
+impl JxlDecoder<'_, '_> {
+    /// Decode a JPEG XL file, see [`JxlDecoder::decode`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be mapped, or a [`DecodeError`]
+    /// when internal decoder fails
+    pub fn decode_file(&self, path: impl AsRef<Path>) -> Result<(Metadata, Pixels), DecodeError> {
+        self.decode(&map(path.as_ref())?)
+    }
+
+    /// Decode a JPEG XL file in pixels of type `T`, see [`JxlDecoder::decode_with`]
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be mapped, or a [`DecodeError`]
+    /// when internal decoder fails
+    pub fn decode_file_with<T: PixelType>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<(Metadata, Vec<T>), DecodeError> {
+        self.decode_with(&map(path.as_ref())?)
+    }
+}
+
+impl JxlDecoder<'_, '_> {
+    /// Decode a JPEG XL image to pixels of type `T` in a new file at `path`, replacing any
+    /// existing one
+    ///
+    /// The file is sized for the image, mapped and decoded into, so large images are never
+    /// held in memory. `Metadata::icc_profile` is not filled in.
+    ///
+    /// # Errors
+    /// Return [`DecodeError::Io`] when the file cannot be created or mapped,
+    /// [`DecodeError::UnsupportedBitWidth`] for [`FileFormat::Pnm`] with floating point
+    /// samples, or a [`DecodeError`] when internal decoder fails
+    pub fn decode_to_file<T: PixelType>(
+        &mut self,
+        data: &[u8],
+        path: impl AsRef<Path>,
+        format: FileFormat,
+    ) -> Result<Metadata, DecodeError> {
+        let (width, height, channels) = self.output_size::<T>(data)?;
+        let (bits, exp) = T::bits_per_sample();
+        let header = match format {
+            FileFormat::Raw => String::new(),
+            FileFormat::Pnm if exp != 0 => return Err(DecodeError::UnsupportedBitWidth(bits)),
+            FileFormat::Pnm => pnm_header(width, height, channels, (1 << bits) - 1),
+        };
+        let stride = width as usize * channels as usize * std::mem::size_of::<T>();
+        let len = header.len() + stride * height as usize;
+
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+        // SAFETY: Documented at the module level, the file is only written while it is mapped
+        let mut mmap = unsafe { MmapMut::map_mut(&file) }?;
+        mmap[..header.len()].copy_from_slice(header.as_bytes());
+
+        let pixel_format = self.pixel_format;
+        if format == FileFormat::Pnm {
+            self.pixel_format = Some(PixelFormat {
+                endianness: Endianness::Big,
+                ..pixel_format.unwrap_or_default()
+            });
+        }
+        let result = self.decode_into_surface::<T>(data, &mut mmap[header.len()..], stride);
+        self.pixel_format = pixel_format;
+
+        let metadata = result?;
+        mmap.flush()?;
+        Ok(metadata)
+    }
+
+    /// Width, height and number of channels of the pixels the decoder returns
+    fn output_size<T: PixelType>(&mut self, data: &[u8]) -> Result<(u32, u32, u32), DecodeError> {
+        let info = self.read_basic_info(data)?;
+        let format = self.resolve_pixel_format(&info, Some(T::pixel_type()))?;
+        Ok((info.xsize, info.ysize, format.num_channels))
+    }
+}
+
+fn pnm_header(width: u32, height: u32, channels: u32, max: u32) -> String {
+    match channels {
+        1 => format!("P5\n{width} {height}\n{max}\n"),
+        3 => format!("P6\n{width} {height}\n{max}\n"),
+        _ => {
+            let tuple_type = if channels == 2 {
+                "GRAYSCALE_ALPHA"
+            } else {
+                "RGB_ALPHA"
+            };
+            format!(
+                "P7\nWIDTH {width}\nHEIGHT {height}\nDEPTH {channels}\nMAXVAL {max}\n\
+                 TUPLTYPE {tuple_type}\nENDHDR\n"
+            )
+        }
+    }
+}
+
+impl JxlEncoder<'_, '_> {
+    /// Encode a JPEG file losslessly, see [`JxlEncoder::encode_jpeg`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError::Io`] when the file cannot be mapped, or an [`EncodeError`]
+    /// when internal encoder fails
+    pub fn encode_jpeg_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<EncoderResult<u8>, EncodeError> {
+        self.encode_jpeg(&map(path.as_ref())?)
+    }
+
+    /// Encode a file of raw interleaved pixels of type `T` in native endianness,
+    /// see [`JxlEncoder::encode`]
+    ///
+    /// # Errors
+    /// Return [`EncodeError::Io`] when the file cannot be mapped, or an [`EncodeError`]
+    /// when internal encoder fails
+    pub fn encode_file<T: PixelType, U: PixelType>(
+        &mut self,
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+    ) -> Result<EncoderResult<U>, EncodeError> {
+        let mmap = map(path.as_ref())?;
+        // SAFETY: Mappings are page aligned, and all pixel types are plain numbers
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                mmap.as_ptr().cast::<T>(),
+                mmap.len() / std::mem::size_of::<T>(),
+            )
+        };
+        self.encode(data, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use testresult::TestResult;
+
+    use crate::{decode::Pixels, decoder_builder, encoder_builder};
+
+    use super::*;
+
+    fn sample(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../samples")
+            .join(name)
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn files() -> TestResult {
+        let decoder = decoder_builder().build()?;
+        let (_, Pixels::Uint16(pixels)) = decoder.decode_file(sample("sample.jxl"))? else {
+            panic!("Unexpected pixel type");
+        };
+        let (_, typed) = decoder.decode_file_with::<u16>(sample("sample.jxl"))?;
+        assert_eq!(pixels, typed);
+
+        assert!(matches!(
+            decoder.decode_file(sample("missing.jxl")),
+            Err(DecodeError::Io(_))
+        ));
+
+        let mut encoder = encoder_builder().use_container(true).build()?;
+        let result = encoder.encode_jpeg_file(sample("sample.jpg"))?;
+        decoder.reconstruct(&result)?;
+
+        let raw = std::env::temp_dir().join("jpegxl-rs-mmap.rgb");
+        std::fs::write(&raw, [0u8; 2 * 2 * 3])?;
+        let result: EncoderResult<u8> = encoder.encode_file::<u8, u8>(&raw, 2, 2)?;
+        std::fs::remove_file(&raw)?;
+        decoder.decode(&result)?;
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn output_files() -> TestResult {
+        let mut decoder = decoder_builder().build()?;
+        let data = std::fs::read(sample("sample.jxl"))?;
+        let (_, pixels) = decoder.decode_with::<u16>(&data)?;
+        let bytes: Vec<u8> = pixels.iter().flat_map(|v| v.to_ne_bytes()).collect();
+
+        let path = std::env::temp_dir().join("jpegxl-rs-mmap-output");
+        let metadata = decoder.decode_to_file::<u16>(&data, &path, FileFormat::Raw)?;
+        assert_eq!(std::fs::read(&path)?, bytes);
+
+        decoder.decode_to_file::<u16>(&data, &path, FileFormat::Pnm)?;
+        let pnm = std::fs::read(&path)?;
+        let header = format!(
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 65535\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            metadata.width, metadata.height
+        );
+        assert_eq!(&pnm[..header.len()], header.as_bytes());
+        let samples: Vec<u16> = pnm[header.len()..]
+            .chunks_exact(2)
+            .map(|v| u16::from_be_bytes([v[0], v[1]]))
+            .collect();
+        assert_eq!(samples, pixels);
+        // The pixel format of the decoder is restored
+        assert!(decoder.pixel_format.is_none());
+
+        assert!(matches!(
+            decoder.decode_to_file::<f32>(&data, &path, FileFormat::Pnm),
+            Err(DecodeError::UnsupportedBitWidth(32))
+        ));
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
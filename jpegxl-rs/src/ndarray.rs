@@ -0,0 +1,105 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `ndarray` crate integration
+
+use ndarray::Array3;
+
+use crate::{
+    common::PixelType,
+    decode::{JxlDecoder, Metadata},
+    DecodeError,
+};
+
+/// Extension trait for [`JxlDecoder`]
+pub trait ToNdarray {
+    /// Decode the JPEG XL image to an [`Array3`] of shape `(height, width, channels)`
+    ///
+    /// Scanline alignment requested by the pixel format is removed.
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when internal decoding fails.
+    fn decode_to_ndarray<T: PixelType>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Array3<T>), DecodeError>;
+}
+
+impl ToNdarray for JxlDecoder<'_, '_> {
+    fn decode_to_ndarray<T: PixelType>(
+        &self,
+        data: &[u8],
+    ) -> Result<(Metadata, Array3<T>), DecodeError> {
+        let (metadata, channels, pixels) = self.decode_unpadded::<T>(data)?;
+        let (width, height) = (metadata.width as usize, metadata.height as usize);
+
+        let array = Array3::from_shape_vec((height, width, channels), pixels)
+            .map_err(|_| DecodeError::GenericError)?;
+        Ok((metadata, array))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decode::PixelFormat,
+        decoder_builder,
+        tests::{SAMPLE_JXL, SAMPLE_JXL_GRAY},
+    };
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn shape() -> TestResult {
+        let decoder = decoder_builder().build()?;
+
+        let (Metadata { width, height, .. }, array) =
+            decoder.decode_to_ndarray::<u8>(SAMPLE_JXL)?;
+        assert_eq!(array.dim(), (height as usize, width as usize, 4));
+
+        let (Metadata { width, height, .. }, array) =
+            decoder.decode_to_ndarray::<u16>(SAMPLE_JXL_GRAY)?;
+        assert_eq!(array.dim(), (height as usize, width as usize, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn aligned() -> TestResult {
+        let mut decoder = decoder_builder()
+            .pixel_format(PixelFormat {
+                num_channels: 3,
+                ..PixelFormat::default()
+            })
+            .build()?;
+        let (_, packed) = decoder.decode_to_ndarray::<f32>(SAMPLE_JXL)?;
+
+        decoder.pixel_format = Some(PixelFormat {
+            num_channels: 3,
+            align: 10,
+            ..PixelFormat::default()
+        });
+        let (_, array) = decoder.decode_to_ndarray::<f32>(SAMPLE_JXL)?;
+        assert_eq!(array, packed);
+
+        Ok(())
+    }
+}
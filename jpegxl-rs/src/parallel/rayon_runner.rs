@@ -0,0 +1,138 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Runner borrowing threads from a `rayon` thread pool
+
+#![cfg(feature = "rayon")]
+#![cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+
+use std::{
+    ffi::c_void,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use jpegxl_sys::parallel_runner::JxlParallelRetCode;
+use rayon::{current_num_threads, current_thread_index, prelude::*, ThreadPool};
+
+use super::{InitFn, JxlParallelRunner, RunFn, RunnerFn};
+
+/// Parallel runner running the jobs of libjxl on a `rayon` thread pool
+///
+/// Applications already using `rayon` avoid starting another set of threads. Calls from
+/// outside the pool block until the jobs are done on it.
+#[derive(Clone, Copy, Default)]
+pub struct RayonRunner<'p> {
+    pool: Option<&'p ThreadPool>,
+}
+
+impl<'p> RayonRunner<'p> {
+    /// Construct on the given thread pool, or the global one if `None`
+    #[must_use]
+    pub fn new(pool: Option<&'p ThreadPool>) -> Self {
+        Self { pool }
+    }
+}
+
+/// Raw pointer which libjxl allows using from any of the runner threads
+#[derive(Clone, Copy)]
+struct Opaque(*mut c_void);
+unsafe impl Send for Opaque {}
+unsafe impl Sync for Opaque {}
+
+impl Opaque {
+    // Closures capturing the whole wrapper, not only the pointer field, stay `Send`
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
+
+unsafe extern "C-unwind" fn rayon_runner(
+    runner_opaque: *mut c_void,
+    jpegxl_opaque: *mut c_void,
+    init_func: InitFn,
+    run_func: RunFn,
+    start_range: u32,
+    end_range: u32,
+) -> JxlParallelRetCode {
+    let runner = &*(runner_opaque as *const RayonRunner);
+    let opaque = Opaque(jpegxl_opaque);
+
+    let run = || {
+        let ret = init_func(opaque.get(), current_num_threads());
+        if ret != 0 {
+            return ret;
+        }
+
+        (start_range..end_range).into_par_iter().for_each(|value| {
+            // Thread indices of the current pool are below its number of threads
+            let thread_id = current_thread_index().unwrap_or(0);
+            run_func(opaque.get(), value, thread_id);
+        });
+        0
+    };
+
+    // Panics of the pool must not unwind into libjxl, so report them as a runner error
+    catch_unwind(AssertUnwindSafe(|| match runner.pool {
+        Some(pool) => pool.install(run),
+        None => run(),
+    }))
+    .unwrap_or(-1)
+}
+
+impl JxlParallelRunner for RayonRunner<'_> {
+    fn runner(&self) -> RunnerFn {
+        rayon_runner
+    }
+
+    fn as_opaque_ptr(&self) -> *mut c_void {
+        (self as *const Self).cast_mut().cast()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{decoder_builder, encode::EncoderResult, encoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_pools() -> TestResult {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build()?;
+        let expected = decoder_builder().build()?.decode_with::<u8>(SAMPLE_JXL)?.1;
+
+        for runner in [RayonRunner::default(), RayonRunner::new(Some(&pool))] {
+            let decoder = decoder_builder().parallel_runner(&runner).build()?;
+            assert_eq!(decoder.decode_with::<u8>(SAMPLE_JXL)?.1, expected);
+
+            let mut encoder = encoder_builder().parallel_runner(&runner).build()?;
+            let _: EncoderResult<u8> = encoder.encode(&expected, 40, 50)?;
+        }
+
+        // Called from a thread of the pool itself
+        let runner = RayonRunner::new(Some(&pool));
+        pool.install(|| {
+            decoder_builder()
+                .parallel_runner(&runner)
+                .build()?
+                .decode(SAMPLE_JXL)
+        })?;
+
+        Ok(())
+    }
+}
@@ -20,7 +20,7 @@ along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 #![cfg(feature = "threads")]
 #![cfg_attr(docsrs, doc(cfg(feature = "threads")))]
 
-use std::{ffi::c_void, ptr::null_mut};
+use std::{cell::Cell, ffi::c_void, num::NonZeroUsize, ptr::null_mut, thread};
 
 #[allow(clippy::wildcard_imports)]
 use jpegxl_sys::resizable_parallel_runner::*;
@@ -30,8 +30,13 @@ use super::{JxlParallelRunner, RunnerFn};
 use crate::{decode::BasicInfo, memory::MemoryManager};
 
 /// Wrapper for resizable thread pool implementation with C++ standard library
+///
+/// By default, the number of threads is picked from the size of each image, capped at
+/// the available parallelism. It can be fixed with [`ResizableRunner::set_threads`]
+/// between runs, without recreating the decoder or encoder using it.
 pub struct ResizableRunner<'mm> {
     runner_ptr: *mut c_void,
+    fixed_threads: Cell<Option<usize>>,
     _memory_manager: Option<&'mm dyn MemoryManager>,
 }
 
@@ -45,14 +50,37 @@ impl<'mm> ResizableRunner<'mm> {
 
         Self {
             runner_ptr,
+            fixed_threads: Cell::new(None),
             _memory_manager: memory_manager,
         }
     }
 
-    /// Set number of threads depending on the size of the image
+    /// Set number of threads depending on the size of the image,
+    /// unless a number was fixed with [`ResizableRunner::set_threads`]
     pub fn set_num_threads(&self, width: u64, height: u64) {
-        let num = unsafe { JxlResizableParallelRunnerSuggestThreads(width, height) };
-        unsafe { JxlResizableParallelRunnerSetThreads(self.runner_ptr, num as usize) };
+        if self.fixed_threads.get().is_some() {
+            return;
+        }
+
+        let suggested = unsafe { JxlResizableParallelRunnerSuggestThreads(width, height) };
+        let available = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let num = usize::try_from(suggested).map_or(available, |n| n.min(available));
+        unsafe { JxlResizableParallelRunnerSetThreads(self.runner_ptr, num) };
+    }
+
+    /// Use `num_threads` threads from now on, or go back to picking the number for
+    /// each image with `None`
+    pub fn set_threads(&self, num_threads: Option<usize>) {
+        self.fixed_threads.set(num_threads);
+        if let Some(num) = num_threads {
+            unsafe { JxlResizableParallelRunnerSetThreads(self.runner_ptr, num) };
+        }
+    }
+
+    /// Number of threads set with [`ResizableRunner::set_threads`]
+    #[must_use]
+    pub fn fixed_threads(&self) -> Option<usize> {
+        self.fixed_threads.get()
     }
 }
 
@@ -60,6 +88,7 @@ impl Default for ResizableRunner<'_> {
     fn default() -> Self {
         Self {
             runner_ptr: unsafe { JxlResizableParallelRunnerCreate(std::ptr::null()) },
+            fixed_threads: Cell::new(None),
             _memory_manager: None,
         }
     }
@@ -87,9 +116,17 @@ impl Drop for ResizableRunner<'_> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use testresult::TestResult;
 
-    use crate::{decoder_builder, memory::tests::BumpManager};
+    use crate::{
+        decoder_builder,
+        encode::{EncoderResult, EncoderSpeed},
+        encoder_builder,
+        memory::tests::BumpManager,
+        parallel::{InitFn, JxlParallelRetCode, RunFn},
+    };
 
     use super::*;
 
@@ -107,4 +144,112 @@ mod tests {
 
         Ok(())
     }
+
+    /// Runner recording the number of threads libjxl is told to use
+    struct Recorder<'r> {
+        inner: &'r ResizableRunner<'r>,
+        threads: Mutex<Vec<usize>>,
+    }
+
+    struct Call<'c> {
+        recorder: &'c Recorder<'c>,
+        jpegxl_opaque: *mut c_void,
+        init_func: InitFn,
+        run_func: RunFn,
+    }
+
+    impl Recorder<'_> {
+        fn take_max(&self) -> Option<usize> {
+            self.threads.lock().ok()?.drain(..).max()
+        }
+    }
+
+    impl JxlParallelRunner for Recorder<'_> {
+        fn runner(&self) -> RunnerFn {
+            unsafe extern "C-unwind" fn runner(
+                runner_opaque: *mut c_void,
+                jpegxl_opaque: *mut c_void,
+                init_func: InitFn,
+                run_func: RunFn,
+                start_range: u32,
+                end_range: u32,
+            ) -> JxlParallelRetCode {
+                let recorder = &*runner_opaque.cast::<Recorder<'_>>();
+                let call = Call {
+                    recorder,
+                    jpegxl_opaque,
+                    init_func,
+                    run_func,
+                };
+                (recorder.inner.runner())(
+                    recorder.inner.as_opaque_ptr(),
+                    std::ptr::addr_of!(call).cast_mut().cast(),
+                    init,
+                    run,
+                    start_range,
+                    end_range,
+                )
+            }
+
+            runner
+        }
+
+        fn as_opaque_ptr(&self) -> *mut c_void {
+            (self as *const Self).cast_mut().cast()
+        }
+
+        fn callback_basic_info(&self, basic_info: &BasicInfo) {
+            self.inner.callback_basic_info(basic_info);
+        }
+    }
+
+    unsafe extern "C-unwind" fn init(opaque: *mut c_void, num_threads: usize) -> i32 {
+        let call = &*opaque.cast::<Call<'_>>();
+        if let Ok(mut threads) = call.recorder.threads.lock() {
+            threads.push(num_threads);
+        }
+        (call.init_func)(call.jpegxl_opaque, num_threads)
+    }
+
+    unsafe extern "C-unwind" fn run(opaque: *mut c_void, value: u32, thread_id: usize) {
+        let call = &*opaque.cast::<Call<'_>>();
+        (call.run_func)(call.jpegxl_opaque, value, thread_id);
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_set_threads() -> TestResult {
+        let parallel_runner = ResizableRunner::default();
+        let recorder = Recorder {
+            inner: &parallel_runner,
+            threads: Mutex::default(),
+        };
+        let decoder = decoder_builder().parallel_runner(&recorder).build()?;
+
+        // Large enough for four groups, so that the work is split between threads
+        let (width, height) = (512, 512);
+        let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let mut encoder = encoder_builder().speed(EncoderSpeed::Lightning).build()?;
+        let image: EncoderResult<u8> = encoder.encode(&pixels, width, height)?;
+
+        parallel_runner.set_threads(Some(3));
+        decoder.decode(&image)?;
+        assert_eq!(parallel_runner.fixed_threads(), Some(3));
+        assert_eq!(recorder.take_max(), Some(3));
+
+        parallel_runner.set_threads(Some(2));
+        decoder.decode(&image)?;
+        assert_eq!(recorder.take_max(), Some(2));
+
+        parallel_runner.set_threads(None);
+        decoder.decode(&image)?;
+        assert_eq!(parallel_runner.fixed_threads(), None);
+        let suggested =
+            unsafe { JxlResizableParallelRunnerSuggestThreads(width.into(), height.into()) };
+        let available = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let expected = usize::try_from(suggested)?.min(available).max(1);
+        assert_eq!(recorder.take_max(), Some(expected));
+
+        Ok(())
+    }
 }
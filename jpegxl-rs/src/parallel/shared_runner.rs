@@ -0,0 +1,150 @@
+/*
+This file is part of jpegxl-rs.
+
+jpegxl-rs is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+jpegxl-rs is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Thread pool shared by many concurrently live decoders or encoders
+
+#![cfg(feature = "threads")]
+#![cfg_attr(docsrs, doc(cfg(feature = "threads")))]
+
+use std::{
+    ffi::c_void,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use jpegxl_sys::{
+    parallel_runner::JxlParallelRetCode, thread_parallel_runner::JxlThreadParallelRunner,
+};
+
+use super::{threads_runner::ThreadsRunner, InitFn, JxlParallelRunner, RunFn, RunnerFn};
+
+/// Thread pool runner which can be cloned and shared across threads
+///
+/// The C++ thread pool can only run one job at a time, so jobs from different
+/// decoders or encoders take turns on the same workers. This bounds the number of
+/// threads regardless of how many images are processed at once.
+///
+/// # Example
+/// ```
+/// # || -> Result<(), Box<dyn std::error::Error>> {
+/// use jpegxl_rs::{decoder_builder, parallel::shared_runner::SharedRunner};
+///
+/// let runner = SharedRunner::default();
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let runner = runner.clone();
+///         std::thread::spawn(move || {
+///             let decoder = decoder_builder().parallel_runner(&runner).build();
+///             decoder.is_ok()
+///         })
+///     })
+///     .collect();
+/// # for h in handles { assert!(h.join().unwrap()); }
+/// # Ok(())
+/// # };
+/// ```
+#[derive(Clone)]
+pub struct SharedRunner {
+    pool: Arc<Mutex<Pool>>,
+}
+
+struct Pool(ThreadsRunner<'static>);
+
+// SAFETY: The pool is created without a memory manager, and the C++ thread pool
+// may be used from any thread as long as calls do not overlap, which the mutex ensures.
+unsafe impl Send for Pool {}
+
+impl SharedRunner {
+    /// Construct with number of threads
+    #[must_use]
+    pub fn new(num_workers: Option<usize>) -> Option<Self> {
+        ThreadsRunner::new(None, num_workers).map(|pool| Self {
+            pool: Arc::new(Mutex::new(Pool(pool))),
+        })
+    }
+}
+
+impl Default for SharedRunner {
+    fn default() -> Self {
+        Self {
+            pool: Arc::new(Mutex::new(Pool(ThreadsRunner::default()))),
+        }
+    }
+}
+
+unsafe extern "C-unwind" fn shared_runner(
+    runner_opaque: *mut c_void,
+    jpegxl_opaque: *mut c_void,
+    init_func: InitFn,
+    run_func: RunFn,
+    start_range: u32,
+    end_range: u32,
+) -> JxlParallelRetCode {
+    let pool = &*(runner_opaque as *const Mutex<Pool>);
+    let pool = pool.lock().unwrap_or_else(PoisonError::into_inner);
+    JxlThreadParallelRunner(
+        pool.0.as_opaque_ptr(),
+        jpegxl_opaque,
+        init_func,
+        run_func,
+        start_range,
+        end_range,
+    )
+}
+
+impl JxlParallelRunner for SharedRunner {
+    fn runner(&self) -> RunnerFn {
+        shared_runner
+    }
+
+    fn as_opaque_ptr(&self) -> *mut c_void {
+        Arc::as_ptr(&self.pool).cast_mut().cast()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{decoder_builder, tests::SAMPLE_JXL};
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_concurrent() -> TestResult {
+        let runner = SharedRunner::new(Some(2)).expect("Failed to create runner");
+        let expected = decoder_builder().build()?.decode(SAMPLE_JXL)?.1;
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let runner = runner.clone();
+                thread::spawn(move || {
+                    let decoder = decoder_builder().parallel_runner(&runner).build()?;
+                    decoder.decode(SAMPLE_JXL).map(|(_, pixels)| pixels)
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let pixels = handle.join().expect("Decoding thread panicked")?;
+            assert_eq!(format!("{pixels:?}"), format!("{expected:?}"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Pool of decoders and encoders, reused between requests of a server
+//!
+//! Rebuilding a decoder or an encoder for every image sets up libjxl again each time. A
+//! [`Pool`] keeps the instances returned to it and hands them out again, reset.
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crate::{decode::SendDecoder, encode::SendEncoder, DecodeError, EncodeError};
+
+type MakeDecoder = dyn Fn() -> Result<SendDecoder<'static, 'static>, DecodeError> + Send + Sync;
+type MakeEncoder = dyn Fn() -> Result<SendEncoder<'static, 'static>, EncodeError> + Send + Sync;
+
+/// Take an idle instance, if any
+fn take<T>(idle: &Mutex<Vec<T>>) -> Option<T> {
+    idle.lock().unwrap_or_else(PoisonError::into_inner).pop()
+}
+
+/// Keep an instance for its next use
+fn give_back<T>(idle: &Mutex<Vec<T>>, instance: T) {
+    idle.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(instance);
+}
+
+/// Counters of a [`Pool`], see [`Pool::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of instances handed out
+    pub checkouts: usize,
+    /// Number of instances built, because none was idle
+    pub created: usize,
+}
+
+impl PoolStats {
+    /// Number of checkouts served by an idle instance
+    #[must_use]
+    pub fn reused(&self) -> usize {
+        self.checkouts - self.created
+    }
+}
+
+/// Pool of decoders and encoders, which can be shared between threads
+///
+/// Instances are [`SendDecoder`] and [`SendEncoder`], so an idle instance is handed out
+/// to whichever thread asks next. The pool holds as many instances as were ever checked
+/// out at once, and drops them with it. A
+/// [`SharedRunner`](crate::parallel::shared_runner::SharedRunner) can be given to all of
+/// them.
+///
+/// Returned encoders are reset, dropping anything queued in them.
+///
+/// # Example
+/// ```
+/// use jpegxl_rs::{decoder_builder, encoder_builder, pool::Pool};
+///
+/// let pool = Pool::new(
+///     || decoder_builder().build_send(None, None),
+///     || encoder_builder().build_send(None, None),
+/// );
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         let mut encoder = pool.encoder()?;
+///         let result = encoder.encode::<u8, u8>(&[0; 3], 1, 1)?;
+///         pool.decoder()?.decode(&result)?;
+///         Ok::<_, jpegxl_rs::Error>(())
+///     });
+/// });
+/// assert_eq!(pool.stats().checkouts, 2);
+/// ```
+pub struct Pool {
+    decoders: Mutex<Vec<SendDecoder<'static, 'static>>>,
+    encoders: Mutex<Vec<SendEncoder<'static, 'static>>>,
+    make_decoder: Box<MakeDecoder>,
+    make_encoder: Box<MakeEncoder>,
+    checkouts: AtomicUsize,
+    created: AtomicUsize,
+}
+
+impl Pool {
+    /// Create with the functions building a decoder or an encoder when none is idle
+    pub fn new<D, E>(make_decoder: D, make_encoder: E) -> Self
+    where
+        D: Fn() -> Result<SendDecoder<'static, 'static>, DecodeError> + Send + Sync + 'static,
+        E: Fn() -> Result<SendEncoder<'static, 'static>, EncodeError> + Send + Sync + 'static,
+    {
+        Self {
+            decoders: Mutex::new(Vec::new()),
+            encoders: Mutex::new(Vec::new()),
+            make_decoder: Box::new(make_decoder),
+            make_encoder: Box::new(make_encoder),
+            checkouts: AtomicUsize::new(0),
+            created: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take an idle decoder, or build one
+    ///
+    /// # Errors
+    /// Return a [`DecodeError`] when building the decoder fails
+    pub fn decoder(&self) -> Result<PooledDecoder<'_>, DecodeError> {
+        let decoder = match take(&self.decoders) {
+            Some(decoder) => decoder,
+            None => self.create(&self.make_decoder)?,
+        };
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        Ok(PooledDecoder {
+            pool: self,
+            decoder: Some(decoder),
+        })
+    }
+
+    /// Take an idle encoder, or build one
+    ///
+    /// # Errors
+    /// Return an [`EncodeError`] when building the encoder fails
+    pub fn encoder(&self) -> Result<PooledEncoder<'_>, EncodeError> {
+        let encoder = match take(&self.encoders) {
+            Some(encoder) => encoder,
+            None => self.create(&self.make_encoder)?,
+        };
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        Ok(PooledEncoder {
+            pool: self,
+            encoder: Some(encoder),
+        })
+    }
+
+    /// Counters since the pool was created
+    #[must_use]
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            checkouts: self.checkouts.load(Ordering::Relaxed),
+            created: self.created.load(Ordering::Relaxed),
+        }
+    }
+
+    fn create<T, E>(&self, make: &dyn Fn() -> Result<T, E>) -> Result<T, E> {
+        let instance = make()?;
+        self.created.fetch_add(1, Ordering::Relaxed);
+        Ok(instance)
+    }
+}
+
+/// Decoder checked out of a [`Pool`], returned to it when dropped
+pub struct PooledDecoder<'pool> {
+    pool: &'pool Pool,
+    decoder: Option<SendDecoder<'static, 'static>>,
+}
+
+impl Deref for PooledDecoder<'_> {
+    type Target = SendDecoder<'static, 'static>;
+
+    fn deref(&self) -> &Self::Target {
+        self.decoder.as_ref().expect("Decoder already returned")
+    }
+}
+
+impl DerefMut for PooledDecoder<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.decoder.as_mut().expect("Decoder already returned")
+    }
+}
+
+impl Drop for PooledDecoder<'_> {
+    fn drop(&mut self) {
+        // The underlying decoder is reset before every image already
+        if let Some(decoder) = self.decoder.take() {
+            give_back(&self.pool.decoders, decoder);
+        }
+    }
+}
+
+/// Encoder checked out of a [`Pool`], reset and returned to it when dropped
+pub struct PooledEncoder<'pool> {
+    pool: &'pool Pool,
+    encoder: Option<SendEncoder<'static, 'static>>,
+}
+
+impl Deref for PooledEncoder<'_> {
+    type Target = SendEncoder<'static, 'static>;
+
+    fn deref(&self) -> &Self::Target {
+        self.encoder.as_ref().expect("Encoder already returned")
+    }
+}
+
+impl DerefMut for PooledEncoder<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.encoder.as_mut().expect("Encoder already returned")
+    }
+}
+
+impl Drop for PooledEncoder<'_> {
+    fn drop(&mut self) {
+        if let Some(mut encoder) = self.encoder.take() {
+            encoder.0.reset();
+            give_back(&self.pool.encoders, encoder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        cms::ColorManagement,
+        decoder_builder,
+        encode::{EncoderResult, Metadata},
+        encoder_builder,
+        tests::{UnusedCms, SAMPLE_JXL},
+    };
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_pool() -> TestResult {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let pool = Pool::new(
+            || decoder_builder().build_send(None, None),
+            || encoder_builder().build_send(None, None),
+        );
+        assert_send_sync(&pool);
+
+        for _ in 0..3 {
+            pool.decoder()?.decode(SAMPLE_JXL)?;
+        }
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                checkouts: 3,
+                created: 1
+            }
+        );
+
+        // Instances in use are not handed out twice
+        let first = pool.decoder()?;
+        let second = pool.decoder()?;
+        assert_eq!(pool.stats().created, 2);
+        drop((first, second));
+        pool.decoder()?;
+        assert_eq!(pool.stats().created, 2);
+
+        // Encoders come back without the queued boxes
+        let mut encoder = pool.encoder()?;
+        encoder.add_metadata(&Metadata::Exif(&[0; 8]), false)?;
+        drop(encoder);
+        let result: EncoderResult<u8> = pool.encoder()?.encode(&[0u8; 3], 1, 1)?;
+        assert!(!result.data.starts_with(b"\0\0\0\x0cJXL "));
+        assert_eq!(pool.stats().reused(), 5);
+
+        // and can queue boxes again
+        let mut encoder = pool.encoder()?;
+        encoder.add_metadata(&Metadata::Exif(&[0; 8]), false)?;
+        let result: EncoderResult<u8> = encoder.encode(&[0u8; 3], 1, 1)?;
+        assert!(result.data.starts_with(b"\0\0\0\x0cJXL "));
+
+        // Other threads reuse the idle instances
+        thread::scope(|s| s.spawn(|| pool.decoder().map(drop)).join()).expect("Thread panicked")?;
+        assert_eq!(pool.stats().created, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_pool_drop() -> TestResult {
+        let cms: Arc<dyn ColorManagement> = Arc::new(UnusedCms);
+        let shared = Arc::clone(&cms);
+        let pool = Arc::new(Pool::new(
+            move || {
+                decoder_builder()
+                    .cms(Arc::clone(&shared))
+                    .build_send(None, None)
+            },
+            || encoder_builder().build_send(None, None),
+        ));
+
+        // The decoder of an exited thread is kept for the next checkout
+        let worker = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.decoder().map(drop))
+        };
+        worker.join().expect("Thread panicked")?;
+        pool.decoder()?;
+        assert_eq!(pool.stats().created, 1);
+        // Held by the build function and the only decoder
+        assert_eq!(Arc::strong_count(&cms), 3);
+
+        drop(pool);
+        assert_eq!(Arc::strong_count(&cms), 1);
+
+        Ok(())
+    }
+}
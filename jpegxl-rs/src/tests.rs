@@ -2,10 +2,30 @@ mod decode;
 mod encode;
 
 pub const SAMPLE_PNG: &[u8] = include_bytes!("../../samples/sample.png");
-const SAMPLE_JPEG: &[u8] = include_bytes!("../../samples/sample.jpg");
+pub const SAMPLE_JPEG: &[u8] = include_bytes!("../../samples/sample.jpg");
 const SAMPLE_EXIF: &[u8] = include_bytes!("../../samples/sample.exif");
 const SAMPLE_XMP: &[u8] = include_bytes!("../../samples/sample.xmp");
 pub const SAMPLE_JXL: &[u8] = include_bytes!("../../samples/sample.jxl");
-const SAMPLE_JXL_JPEG: &[u8] = include_bytes!("../../samples/sample_jpg.jxl");
+pub const SAMPLE_JXL_JPEG: &[u8] = include_bytes!("../../samples/sample_jpg.jxl");
 pub const SAMPLE_JXL_GRAY: &[u8] = include_bytes!("../../samples/sample_grey.jxl");
+const SAMPLE_JXL_ROTATED: &[u8] = include_bytes!("../../samples/rotated.jxl");
+const SAMPLE_JXL_ROTATED_PREVIEW: &[u8] = include_bytes!("../../samples/rotated_preview.jxl");
+const SAMPLE_JXL_20BIT: &[u8] = include_bytes!("../../samples/20bit.jxl");
+const SAMPLE_JXL_24BIT: &[u8] = include_bytes!("../../samples/24bit.jxl");
 const SAMPLE_JXL_2BIT: &[u8] = include_bytes!("../../samples/2bit.jxl");
+const SAMPLE_JXL_ANIMATED: &[u8] = include_bytes!("../../samples/animated.jxl");
+
+/// Color management system never asked for a transform, held to count the instances
+/// referring to it
+pub struct UnusedCms;
+
+impl crate::cms::ColorManagement for UnusedCms {
+    fn transform(
+        &self,
+        _input: crate::cms::CmsProfile<'_>,
+        _output: crate::cms::CmsProfile<'_>,
+        _intensity_target: f32,
+    ) -> Option<Box<dyn crate::cms::ColorTransform>> {
+        None
+    }
+}
@@ -23,9 +23,14 @@ use pretty_assertions::assert_eq;
 use testresult::TestResult;
 
 use crate::{
-    common::Endianness,
-    decode::{Data, Metadata, PixelFormat, Pixels},
-    decoder_builder, DecodeError,
+    common::{DataType, Endianness},
+    decode::{
+        decode_to_srgb8, decode_to_srgb8_on, ColorProfileTarget, Completeness, Data, Events,
+        Metadata, PixelFormat, Pixels, Status,
+    },
+    decoder_builder,
+    encode::{EncoderFrame, EncoderResult},
+    encoder_builder, CancellationToken, DecodeError,
 };
 #[cfg(feature = "threads")]
 use crate::{ResizableRunner, ThreadsRunner};
@@ -51,13 +56,15 @@ fn simple() -> TestResult {
             width,
             height,
             icc_profile,
+            animation,
             ..
         },
         data,
     ) = decoder.decode(super::SAMPLE_JXL)?;
+    assert!(animation.is_none());
 
     let Pixels::Uint16(data) = data else {
-        return Err("Failed to decode".into());
+        panic!("Failed to decode");
     };
 
     assert_eq!(data.len(), (width * height * 4) as usize);
@@ -67,32 +74,142 @@ fn simple() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn color_profile_target() -> TestResult {
+    let mut decoder = decoder_builder()
+        .icc_profile(true)
+        .color_profile_target(ColorProfileTarget::Original)
+        .build()?;
+    let original = decoder.decode(super::SAMPLE_JXL)?.0.icc_profile;
+    let original = original.expect("ICC profile not retrieved");
+    lcms2::Profile::new_icc(&original)?;
+
+    decoder.color_profile_target = Some(ColorProfileTarget::Data);
+    let data = decoder.decode_with::<f32>(super::SAMPLE_JXL)?.0.icc_profile;
+    let data = data.expect("ICC profile not retrieved");
+    lcms2::Profile::new_icc(&data)?;
+
+    // The tabulated curve has no JPEG XL color encoding, so the XYB pixels
+    // are returned in linear sRGB instead of the original profile
+    let xy = |x, y| lcms2::CIExyY { x, y, Y: 1.0 };
+    let curve: Vec<u16> = (0..=255).map(|i| i * i / 255 * i / 255 * 257).collect();
+    let curve = lcms2::ToneCurve::new_tabulated(&curve);
+    let icc = lcms2::Profile::new_rgb(
+        &xy(0.3127, 0.329),
+        &lcms2::CIExyYTRIPLE {
+            Red: xy(0.64, 0.33),
+            Green: xy(0.3, 0.6),
+            Blue: xy(0.15, 0.06),
+        },
+        &[&curve, &curve, &curve],
+    )?
+    .icc()?;
+    let sample =
+        image::load_from_memory_with_format(super::SAMPLE_PNG, image::ImageFormat::Png)?.to_rgb8();
+    let mut encoder = encoder_builder().icc_profile(icc.clone()).build()?;
+    let xyb: EncoderResult<u8> =
+        encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+
+    decoder.color_profile_target = Some(ColorProfileTarget::Original);
+    let original = decoder.decode_with::<f32>(&xyb)?.0.icc_profile;
+    assert_eq!(original, Some(icc));
+
+    decoder.color_profile_target = Some(ColorProfileTarget::Data);
+    let data = decoder.decode_with::<f32>(&xyb)?.0.icc_profile;
+    let data = lcms2::Profile::new_icc(&data.expect("ICC profile not retrieved"))?;
+    let lcms2::Tag::ToneCurve(trc) = data.read_tag(lcms2::TagSignature::RedTRCTag) else {
+        panic!("No transfer curve in the data profile");
+    };
+    assert!(trc.is_linear());
+
+    Ok(())
+}
+
 #[test]
 fn sample_2bit() -> TestResult {
     let decoder = decoder_builder().build()?;
 
     let (Metadata { width, height, .. }, data) = decoder.decode(super::SAMPLE_JXL_2BIT)?;
     let Pixels::Uint8(data) = data else {
-        return Err("Failed to decode".into());
+        panic!("Failed to decode");
     };
     assert_eq!(data.len(), (width * height * 3) as usize);
 
     Ok(())
 }
 
+#[test]
+fn sample_20bit() -> TestResult {
+    let decoder = decoder_builder().build()?;
+
+    // Lossless 8x4 RGB image, encoded from known 20-bit samples
+    let (metadata, data) = decoder.decode_u32(super::SAMPLE_JXL_20BIT)?;
+    assert_eq!(metadata.bits_per_sample, 20);
+    let expected: Vec<u32> = (0..8 * 4 * 3)
+        .map(|i| (i * 40503 + 7) % (1 << 20))
+        .collect();
+    assert_eq!(data, expected);
+
+    // Lossless 25x1 RGB image of the 24-bit samples around each power of two
+    let (metadata, data) = decoder.decode_u32(super::SAMPLE_JXL_24BIT)?;
+    assert_eq!(metadata.bits_per_sample, 24);
+    let max = (1 << 24) - 1;
+    let expected: Vec<u32> = (0..=24)
+        .flat_map(|k| [(1 << k) - 1, (1 << k).min(max), ((1 << k) + 1).min(max)])
+        .collect();
+    assert_eq!(data, expected);
+
+    // Narrower images are scaled to their own bit depth
+    let (metadata, data) = decoder.decode_u32(super::SAMPLE_JXL_2BIT)?;
+    assert_eq!(metadata.bits_per_sample, 2);
+    assert!(data.iter().all(|&v| v < 4));
+
+    Ok(())
+}
+
 #[test]
 fn sample_gray() -> TestResult {
     let decoder = decoder_builder().build()?;
 
     let (Metadata { width, height, .. }, data) = decoder.decode(super::SAMPLE_JXL_GRAY)?;
     let Pixels::Uint16(data) = data else {
-        return Err("Failed to decode".into());
+        panic!("Failed to decode");
     };
     assert_eq!(data.len(), (width * height) as usize);
 
     Ok(())
 }
 
+#[test]
+fn orientation() -> TestResult {
+    use crate::decode::Orientation;
+
+    let mut decoder = decoder_builder().build()?;
+    let metadata = decoder.decode_with::<u8>(super::SAMPLE_JXL)?.0;
+    assert_eq!(metadata.applied_orientation, Orientation::Identity);
+    assert_eq!(
+        (metadata.oriented_width, metadata.oriented_height),
+        (metadata.width, metadata.height)
+    );
+
+    // Stored as 3x2, displayed as 2x3
+    let (metadata, data) = decoder.decode_with::<u8>(super::SAMPLE_JXL_ROTATED)?;
+    assert_eq!((metadata.width, metadata.height), (2, 3));
+    assert_eq!((metadata.oriented_width, metadata.oriented_height), (2, 3));
+    assert_eq!(metadata.orientation, Orientation::Identity);
+    assert_eq!(metadata.applied_orientation, Orientation::Rotate90Cw);
+    assert_eq!(data.len(), 2 * 3 * 3);
+
+    decoder.skip_reorientation = Some(true);
+    let metadata = decoder.decode_with::<u8>(super::SAMPLE_JXL_ROTATED)?.0;
+    assert_eq!((metadata.width, metadata.height), (3, 2));
+    assert_eq!((metadata.oriented_width, metadata.oriented_height), (2, 3));
+    assert_eq!(metadata.orientation, Orientation::Rotate90Cw);
+    assert_eq!(metadata.applied_orientation, Orientation::Identity);
+
+    Ok(())
+}
+
 #[test]
 fn pixel_types() -> TestResult {
     let mut decoder = decoder_builder().build()?;
@@ -117,6 +234,404 @@ fn pixel_types() -> TestResult {
     });
     decoder.decode_with::<f16>(super::SAMPLE_JXL)?;
 
+    // Override the data type picked from the bit depth
+    decoder.pixel_format = Some(PixelFormat {
+        data_type: Some(DataType::Float),
+        ..Default::default()
+    });
+    assert!(matches!(
+        decoder.decode(super::SAMPLE_JXL)?.1,
+        Pixels::Float(_)
+    ));
+    // but not the one of the requested pixel type
+    decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+
+    Ok(())
+}
+
+#[test]
+fn lenient() -> TestResult {
+    let decoder = decoder_builder().build()?;
+
+    let (Metadata { width, height, .. }, full) = decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+    let (_, data, completeness) = decoder.decode_lenient_with::<u8>(super::SAMPLE_JXL)?;
+    assert_eq!(completeness, Completeness::Complete);
+    assert_eq!(data, full);
+
+    let (metadata, data, completeness) =
+        decoder.decode_lenient_with::<u8>(&super::SAMPLE_JXL[..super::SAMPLE_JXL.len() / 2])?;
+    assert_eq!(
+        completeness,
+        Completeness::Truncated {
+            bytes_missing: None
+        }
+    );
+    assert_eq!((metadata.width, metadata.height), (width, height));
+    assert_eq!(data.len(), full.len());
+
+    // Container knows the size of its boxes
+    let cut = super::SAMPLE_JXL_JPEG.len() - 100;
+    let (_, _, completeness) = decoder.decode_lenient_with::<u8>(&super::SAMPLE_JXL_JPEG[..cut])?;
+    assert_eq!(
+        completeness,
+        Completeness::Truncated {
+            bytes_missing: Some(100)
+        }
+    );
+
+    // Nothing to recover
+    assert!(decoder
+        .decode_lenient_with::<u8>(&super::SAMPLE_JXL[..8])
+        .is_err());
+
+    // Malformed box sizes must not hang the size computation
+    let mut malformed = super::SAMPLE_JXL_JPEG[..12].to_vec();
+    malformed.extend_from_slice(b"\0\0\0\x01jxlc\0\0\0\0\0\0\0\0");
+    if let Ok((_, _, completeness)) = decoder.decode_lenient_with::<u8>(&malformed) {
+        assert_eq!(
+            completeness,
+            Completeness::Truncated {
+                bytes_missing: None
+            }
+        );
+    }
+    decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+
+    Ok(())
+}
+
+#[test]
+fn reader() -> TestResult {
+    let decoder = decoder_builder().build()?;
+
+    for sample in [super::SAMPLE_JXL, super::SAMPLE_JXL_ROTATED] {
+        let (expected_metadata, expected) = decoder.decode_with::<u8>(sample)?;
+        let (metadata, pixels) = decoder.decode_reader_with::<u8>(Cursor::new(sample))?;
+        assert_eq!(
+            (metadata.width, metadata.applied_orientation),
+            (
+                expected_metadata.width,
+                expected_metadata.applied_orientation
+            )
+        );
+        assert!(pixels == expected);
+    }
+    decoder.decode_reader(super::SAMPLE_JXL)?;
+
+    assert!(matches!(
+        decoder.decode_reader_with::<u8>(&[0u8; 4][..]),
+        Err(DecodeError::InvalidInput)
+    ));
+    assert!(decoder
+        .decode_reader_with::<u8>(&super::SAMPLE_JXL[..super::SAMPLE_JXL.len() / 2])
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn rows() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    let (Metadata { width, height, .. }, full) = decoder.decode_with::<u16>(super::SAMPLE_JXL)?;
+
+    let mut rows = decoder.decode_rows::<u16>(super::SAMPLE_JXL)?;
+    assert_eq!(
+        (rows.basic_info().xsize, rows.basic_info().ysize),
+        (width, height)
+    );
+
+    let mut image = vec![0; full.len()];
+    let mut count = 0;
+    for chunk in &mut rows {
+        let chunk = chunk?;
+        let start = (chunk.y * width as usize + chunk.x) * 4;
+        image[start..start + chunk.data.len()].copy_from_slice(&chunk.data);
+        count += chunk.data.len();
+    }
+    drop(rows);
+    assert_eq!(count, full.len());
+    assert!(image == full);
+
+    assert!(decoder.decode_rows::<u8>(&super::SAMPLE_JXL[..8]).is_err());
+    decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+
+    Ok(())
+}
+
+#[test]
+fn thumbnail() -> TestResult {
+    let decoder = decoder_builder().build()?;
+
+    // 40x50 sample
+    let thumb = decoder.decode_thumbnail(super::SAMPLE_JXL, 25)?;
+    assert_eq!((thumb.width, thumb.height), (20, 25));
+    assert_eq!(thumb.data.len(), 20 * 25 * 3);
+
+    let thumb = decoder.decode_thumbnail(super::SAMPLE_JXL_GRAY, 16)?;
+    assert_eq!(thumb.width.max(thumb.height), 16);
+    assert_eq!(thumb.data.len(), (thumb.width * thumb.height * 3) as usize);
+
+    // Small images are not upscaled
+    let thumb = decoder.decode_thumbnail(super::SAMPLE_JXL_ROTATED, 64)?;
+    assert_eq!((thumb.width, thumb.height), (2, 3));
+
+    // 16x8 image rotated 90 degrees, with an 8x4 preview
+    let thumb = decoder.decode_thumbnail(super::SAMPLE_JXL_ROTATED_PREVIEW, 64)?;
+    assert_eq!((thumb.width, thumb.height), (4, 8));
+    assert_eq!(thumb.data.len(), 4 * 8 * 3);
+
+    let decoder = decoder_builder().skip_reorientation(true).build()?;
+    let thumb = decoder.decode_thumbnail(super::SAMPLE_JXL_ROTATED_PREVIEW, 64)?;
+    assert_eq!((thumb.width, thumb.height), (8, 4));
+
+    Ok(())
+}
+
+#[test]
+fn srgb8() -> TestResult {
+    let decoder = decoder_builder().build()?;
+    let (Metadata { width, height, .. }, rgba) = decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+
+    let (rgb, w, h) = decode_to_srgb8_on(super::SAMPLE_JXL, [0, 0, 255])?;
+    assert_eq!((w, h), (width, height));
+    assert_eq!(rgb.len(), (width * height * 3) as usize);
+    for (rgb, rgba) in rgb.chunks_exact(3).zip(rgba.chunks_exact(4)) {
+        match rgba[3] {
+            255 => assert_eq!(rgb, &rgba[..3]),
+            0 => assert_eq!(rgb, [0, 0, 255]),
+            _ => {}
+        }
+    }
+
+    let (rgb, w, h) = decode_to_srgb8(super::SAMPLE_JXL_GRAY)?;
+    assert_eq!(rgb.len(), (w * h * 3) as usize);
+    assert!(rgb.chunks_exact(3).all(|p| p[0] == p[1] && p[1] == p[2]));
+
+    let (_, w, h) = decode_to_srgb8(super::SAMPLE_JXL_ROTATED)?;
+    assert_eq!((w, h), (2, 3));
+
+    Ok(())
+}
+
+#[test]
+fn surface() -> TestResult {
+    let decoder = decoder_builder().build()?;
+
+    let (Metadata { width, height, .. }, packed) = decoder.decode_with::<u8>(super::SAMPLE_JXL)?;
+    let row_size = width as usize * 4;
+    let stride = row_size + 13;
+
+    let mut surface = vec![0xAA; stride * height as usize];
+    decoder.decode_into_surface::<u8>(super::SAMPLE_JXL, &mut surface, stride)?;
+    for (row, packed_row) in surface.chunks(stride).zip(packed.chunks(row_size)) {
+        assert_eq!(&row[..row_size], packed_row);
+        assert!(row[row_size..].iter().all(|&b| b == 0xAA));
+    }
+
+    assert!(matches!(
+        decoder.decode_into_surface::<u8>(super::SAMPLE_JXL, &mut surface, row_size - 1),
+        Err(DecodeError::SurfaceTooSmall)
+    ));
+    assert!(matches!(
+        decoder.decode_into_surface::<u8>(super::SAMPLE_JXL, &mut surface[..stride], stride),
+        Err(DecodeError::SurfaceTooSmall)
+    ));
+    // The size of the rows overflows
+    assert!(matches!(
+        decoder.decode_into_surface::<u8>(super::SAMPLE_JXL, &mut surface, usize::MAX / 2),
+        Err(DecodeError::SurfaceTooSmall)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn bytes() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    let (_, samples) = decoder.decode_with::<u16>(super::SAMPLE_JXL)?;
+
+    decoder.pixel_format = Some(PixelFormat {
+        endianness: Endianness::Big,
+        ..Default::default()
+    });
+    let (_, bytes) = decoder.decode_bytes_with::<u16>(super::SAMPLE_JXL)?;
+    assert_eq!(bytes.len(), samples.len() * 2);
+    assert!(bytes
+        .chunks_exact(2)
+        .zip(&samples)
+        .all(|(b, &s)| b == s.to_be_bytes()));
+
+    decoder.pixel_format = Some(PixelFormat {
+        endianness: Endianness::Little,
+        ..Default::default()
+    });
+    let (_, bytes) = decoder.decode_bytes_with::<u16>(super::SAMPLE_JXL)?;
+    assert!(bytes
+        .chunks_exact(2)
+        .zip(&samples)
+        .all(|(b, &s)| b == s.to_le_bytes()));
+
+    Ok(())
+}
+
+#[test]
+fn shared_input() -> TestResult {
+    // Reference-counted buffers deref to the input slice without a copy
+    let data = bytes::Bytes::from_static(super::SAMPLE_JXL);
+    let decoder = decoder_builder().build()?;
+    decoder.decode(&data)?;
+    decoder.decode_with::<u8>(&data.slice(..))?;
+
+    Ok(())
+}
+
+#[test]
+fn planar() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+
+    let (Metadata { width, height, .. }, interleaved) =
+        decoder.decode_with::<u16>(super::SAMPLE_JXL)?;
+    let (_, planes) = decoder.decode_planar_with::<u16>(super::SAMPLE_JXL)?;
+    assert_eq!(planes.len(), 4);
+    for (c, plane) in planes.iter().enumerate() {
+        assert_eq!(plane.len(), (width * height) as usize);
+        assert!(plane
+            .iter()
+            .zip(interleaved.iter().skip(c).step_by(4))
+            .all(|(a, b)| a == b));
+    }
+
+    // Padding is removed from each plane
+    decoder.pixel_format = Some(PixelFormat {
+        num_channels: 3,
+        align: 64,
+        ..Default::default()
+    });
+    let (_, planes) = decoder.decode_planar_with::<u8>(super::SAMPLE_JXL)?;
+    assert_eq!(planes.len(), 3);
+    assert!(planes
+        .iter()
+        .all(|plane| plane.len() == (width * height) as usize));
+
+    Ok(())
+}
+
+#[test]
+fn count_frames() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    assert_eq!(decoder.count_frames(super::SAMPLE_JXL)?, 1);
+
+    let pixels = vec![0u8; 4 * 4 * 3];
+    let frame = EncoderFrame::new(&pixels);
+    let result: EncoderResult<u8> = encoder_builder()
+        .build()?
+        .multiple(4, 4)?
+        .add_frame(&frame)?
+        .add_frame(&frame)?
+        .encode()?;
+
+    // Layers without duration are merged into one displayed frame
+    assert_eq!(decoder.count_frames(&result)?, 1);
+    decoder.coalescing = Some(false);
+    assert_eq!(decoder.count_frames(&result)?, 2);
+
+    assert!(decoder.count_frames(&[0x00, 0x00]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn frames() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    let frames = decoder
+        .frames::<u16>(super::SAMPLE_JXL)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].pixels.len(), 40 * 50 * 4);
+    assert_eq!(frames[0].header.is_last, jpegxl_sys::types::JxlBool::True);
+
+    let mut frames = decoder.frames::<u8>(&super::SAMPLE_JXL[..100])?;
+    assert!(frames.next().is_some_and(|f| f.is_err()));
+    assert!(frames.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn animation() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+
+    // Lossless 8x8 RGB animation at 100 ticks per second, looping twice, with three flat
+    // frames lasting 10, 20 and 30 ticks
+    let (metadata, _) = decoder.decode(super::SAMPLE_JXL_ANIMATED)?;
+    let animation = metadata.animation.expect("Animation header not read");
+    assert_eq!(
+        (animation.tps_numerator, animation.tps_denominator),
+        (100, 1)
+    );
+    assert_eq!(animation.num_loops, 2);
+    assert!(!animation.have_timecodes);
+    assert_eq!(decoder.count_frames(super::SAMPLE_JXL_ANIMATED)?, 3);
+
+    let frames = decoder
+        .frames::<u8>(super::SAMPLE_JXL_ANIMATED)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let durations: Vec<_> = frames
+        .iter()
+        .map(|frame| animation.ticks_to_duration(frame.header.duration))
+        .collect();
+    assert_eq!(
+        durations,
+        [100, 200, 300].map(std::time::Duration::from_millis)
+    );
+    for (i, frame) in (1..).zip(&frames) {
+        assert!(frame.pixels.iter().all(|&v| v == i * 60));
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn serde() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    let (metadata, _) = decoder.decode(super::SAMPLE_JXL)?;
+    let json = serde_json::to_value(&metadata)?;
+    assert_eq!(json["width"], 40);
+    assert_eq!(json["orientation"], "Identity");
+    assert_eq!(json["animation"], serde_json::Value::Null);
+
+    let frames = decoder
+        .frames::<u8>(super::SAMPLE_JXL)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let json = serde_json::to_value(&frames[0].header)?;
+    assert_eq!(json["is_last"], "True");
+    assert_eq!(json["layer_info"]["blend_info"]["blendmode"], "Replace");
+
+    let boxes =
+        crate::utils::container_boxes(super::SAMPLE_JXL_JPEG).collect::<Result<Vec<_>, _>>()?;
+    let json = serde_json::to_value(&boxes)?;
+    assert_eq!(json[0]["box_type"], "JXL ");
+    assert_eq!(json[0]["size"], 12);
+    assert!(json[0].get("payload").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn cancellation() -> TestResult {
+    let token = CancellationToken::new();
+    let decoder = decoder_builder().cancellation(token.clone()).build()?;
+
+    token.cancel();
+    assert!(matches!(
+        decoder.decode(super::SAMPLE_JXL),
+        Err(DecodeError::Cancelled)
+    ));
+
+    token.reset();
+    decoder.decode(super::SAMPLE_JXL)?;
+
     Ok(())
 }
 
@@ -126,7 +641,7 @@ fn jpeg() -> TestResult {
 
     let (_, data) = decoder.reconstruct(super::SAMPLE_JXL_JPEG)?;
     let Data::Jpeg(data) = data else {
-        return Err("Failed to reconstruct".into());
+        panic!("Failed to reconstruct");
     };
 
     let jpeg = image::codecs::jpeg::JpegDecoder::new(Cursor::new(data))?;
@@ -149,6 +664,7 @@ fn builder() -> TestResult {
     let mut decoder = decoder_builder()
         .pixel_format(PixelFormat {
             num_channels: 3,
+            data_type: None,
             endianness: Endianness::Big,
             align: 10,
         })
@@ -180,3 +696,96 @@ fn builder() -> TestResult {
 
     Ok(())
 }
+
+#[test]
+fn session() -> TestResult {
+    let mut decoder = decoder_builder().build()?;
+    let events = Events::new()
+        .want_basic_info()
+        .want_color_profile()
+        .want_frame()
+        .want_full_image();
+
+    let mut session = decoder.session(super::SAMPLE_JXL, events)?;
+    let mut info = None;
+    let mut icc_profile = None;
+    let mut frames = 0;
+    let mut pixels = None;
+    loop {
+        match session.process()? {
+            Status::BasicInfo => info = Some(session.basic_info()?),
+            Status::ColorEncoding => icc_profile = Some(session.icc_profile()?),
+            Status::Frame => {
+                assert!(session.frame_header().is_ok());
+                frames += 1;
+            }
+            Status::NeedImageOutBuffer => session.set_image_out_buffer::<u8>()?,
+            Status::FullImage => pixels = session.take_pixels(),
+            Status::Success => break,
+            s => panic!("Unexpected status: {s:?}"),
+        }
+    }
+    drop(session);
+
+    let info = info.expect("Basic info not emitted");
+    lcms2::Profile::new_icc(&icc_profile.expect("ICC profile not emitted"))?;
+    assert_eq!(frames, 1);
+    let Some(Pixels::Uint8(pixels)) = pixels else {
+        panic!("Failed to decode");
+    };
+    assert_eq!(pixels.len(), (info.xsize * info.ysize * 4) as usize);
+
+    // The decoder is reset and can be used again
+    decoder.decode(super::SAMPLE_JXL)?;
+    assert!(decoder.session(&[0; 64], events).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn session_boxes() -> TestResult {
+    let sample =
+        image::load_from_memory_with_format(super::SAMPLE_PNG, image::ImageFormat::Png)?.to_rgb8();
+    // The compressed XMP packet decompresses to more than its box size, so the box
+    // buffer has to grow
+    let xmp = super::SAMPLE_XMP.repeat(32);
+    let mut encoder = encoder_builder().build()?;
+    encoder.add_metadata(&crate::encode::Metadata::Exif(super::SAMPLE_EXIF), false)?;
+    encoder.add_metadata(&crate::encode::Metadata::Xmp(&xmp), true)?;
+    let result: EncoderResult<u8> =
+        encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+
+    let mut decoder = decoder_builder().decompress(true).build()?;
+    let mut session = decoder.session(&result, Events::new().want_boxes())?;
+    let mut boxes = vec![];
+    let mut box_type = None;
+    loop {
+        let status = session.process()?;
+        if let Status::Box | Status::Success = status {
+            if let (Some(box_type), Some(contents)) = (box_type.take(), session.take_box_contents())
+            {
+                boxes.push((box_type, contents));
+            }
+        }
+        match status {
+            Status::Box => {
+                box_type = Some(session.box_type()?);
+                session.set_box_buffer()?;
+            }
+            Status::BoxNeedMoreOutput => session.grow_box_buffer()?,
+            Status::Success => break,
+            _ => {}
+        }
+    }
+
+    let contents = |ty: &[u8; 4]| {
+        boxes
+            .iter()
+            .find(|(t, _)| t == ty)
+            .map(|(_, c)| c.as_slice())
+    };
+    assert_eq!(contents(b"Exif"), Some(super::SAMPLE_EXIF));
+    assert_eq!(contents(b"xml "), Some(xmp.as_slice()));
+
+    Ok(())
+}
@@ -24,7 +24,9 @@ use crate::decode::Data;
 use crate::{
     decoder_builder,
     encode::{ColorEncoding, EncoderFrame, EncoderResult, Metadata},
-    encoder_builder, Endianness,
+    encoder_builder,
+    utils::container_boxes,
+    EncodeError, Endianness, PixelFormat,
 };
 #[cfg(feature = "threads")]
 use crate::{encode::EncoderSpeed, ResizableRunner, ThreadsRunner};
@@ -62,7 +64,7 @@ fn jpeg() -> TestResult {
     let res = encoder.encode_jpeg(super::SAMPLE_JPEG)?;
 
     let (_, Data::Jpeg(reconstructed)) = decoder_builder().build()?.reconstruct(&res)? else {
-        return Err("Failed to reconstruct JPEG".into());
+        panic!("Failed to reconstruct JPEG");
     };
 
     assert_eq!(super::SAMPLE_JPEG, reconstructed);
@@ -80,6 +82,17 @@ fn metadata() -> TestResult {
     let _res: EncoderResult<u8> =
         encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
 
+    // Boxes are enabled again after a reset
+    encoder.add_metadata(&Metadata::Exif(super::SAMPLE_EXIF), false)?;
+    encoder.reset();
+    encoder.add_metadata(&Metadata::Exif(super::SAMPLE_EXIF), false)?;
+    let res: EncoderResult<u8> =
+        encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+    let exif = container_boxes(&res)
+        .filter(|b| b.as_ref().is_ok_and(|b| &b.box_type == b"Exif"))
+        .count();
+    assert_eq!(exif, 1);
+
     Ok(())
 }
 
@@ -113,12 +126,14 @@ fn builder() -> TestResult {
         Metadata {
             num_color_channels,
             has_alpha_channel,
+            alpha_premultiplied,
             ..
         },
         _,
     ) = decoder.decode(&res)?;
     assert_eq!(num_color_channels, 3);
     assert!(has_alpha_channel);
+    assert!(!alpha_premultiplied);
 
     Ok(())
 }
@@ -164,6 +179,60 @@ fn pixel_type() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn bit_depth() -> TestResult {
+    let mut encoder = encoder_builder()
+        .lossless(true)
+        .uses_original_profile(true)
+        .build()?;
+    let decoder = decoder_builder().build()?;
+    let data: Vec<u16> = (0..8 * 4 * 3).map(|i| i * 10).collect();
+    let frame = EncoderFrame::new(&data);
+
+    let res: EncoderResult<u16> = encoder.encode_frame_with_bit_depth(&frame, 8, 4, 10)?;
+    let (metadata, pixels) = decoder.decode_with::<u16>(&res)?;
+    assert_eq!(metadata.bits_per_sample, 10);
+    let expected = data
+        .iter()
+        .map(|&v| u16::try_from((u32::from(v) * 65535 + 511) / 1023))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(pixels, expected);
+
+    let res: EncoderResult<u16> = encoder.encode_frame(&frame, 8, 4)?;
+    assert_eq!(decoder.decode(&res)?.0.bits_per_sample, 16);
+
+    assert!(matches!(
+        encoder.encode_frame_with_bit_depth::<u16, u8>(&frame, 8, 4, 10),
+        Err(crate::EncodeError::NotSupported)
+    ));
+    assert!(matches!(
+        encoder.encode_frame_with_bit_depth::<u16, f32>(&frame, 8, 4, 10),
+        Err(crate::EncodeError::NotSupported)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn cancellation() -> TestResult {
+    let token = crate::CancellationToken::new();
+    let mut encoder = encoder_builder().cancellation(token.clone()).build()?;
+    let data = [0u8; 3 * 4];
+
+    token.cancel();
+    assert!(matches!(
+        encoder.encode::<u8, u8>(&data, 2, 2),
+        Err(crate::EncodeError::Cancelled)
+    ));
+
+    // The encoder is left ready for the next image
+    token.reset();
+    let res: EncoderResult<u8> = encoder.encode(&data, 2, 2)?;
+    decoder_builder().build()?.decode(&res)?;
+
+    Ok(())
+}
+
 #[test]
 fn multi_frames() -> TestResult {
     let sample = get_sample().to_rgb8();
@@ -172,11 +241,15 @@ fn multi_frames() -> TestResult {
     let frame = EncoderFrame::new(sample.as_raw())
         .endianness(Endianness::Native)
         .align(0);
+    let same_frame = EncoderFrame::new(sample.as_raw()).pixel_format(PixelFormat {
+        num_channels: 3,
+        ..PixelFormat::default()
+    });
 
     let result: EncoderResult<f32> = encoder
         .multiple(sample.width(), sample.height())?
         .add_frame(&frame)?
-        .add_frame(&frame)?
+        .add_frame(&same_frame)?
         .encode()?;
     let decoder = decoder_builder().build()?;
     let _res = decoder.decode(&result)?;
@@ -189,6 +262,29 @@ fn multi_frames() -> TestResult {
         .encode()?;
     let _res = decoder.reconstruct(&result)?;
 
+    // Written in chunks of the initial buffer
+    let mut encoder = encoder_builder().init_buffer_size(64).build()?;
+    let expected: EncoderResult<u8> = encoder
+        .multiple(sample.width(), sample.height())?
+        .add_frame(&frame)?
+        .encode()?;
+    let mut output = vec![];
+    encoder
+        .multiple::<u8>(sample.width(), sample.height())?
+        .add_frame(&frame)?
+        .encode_to(&mut output)?;
+    assert!(output == expected.data);
+
+    let mut full = [0u8; 64];
+    assert!(matches!(
+        encoder
+            .multiple::<u8>(sample.width(), sample.height())?
+            .add_frame(&frame)?
+            .encode_to(&mut full[..]),
+        Err(EncodeError::Io(_))
+    ));
+    encoder.encode::<u8, u8>(sample.as_raw(), sample.width(), sample.height())?;
+
     Ok(())
 }
 
@@ -220,11 +316,51 @@ fn gray() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn color_presets() -> TestResult {
+    let sample = get_sample().to_rgb8();
+    let decoder = decoder_builder().icc_profile(true).build()?;
+
+    let mut profiles = vec![];
+    for preset in [
+        ColorEncoding::DisplayP3,
+        ColorEncoding::LinearDisplayP3,
+        ColorEncoding::Rec709,
+        ColorEncoding::Rec2020,
+        ColorEncoding::LinearRec2020,
+        ColorEncoding::Rec2100Pq,
+        ColorEncoding::Rec2100Hlg,
+        ColorEncoding::ProPhoto,
+        ColorEncoding::LinearProPhoto,
+    ] {
+        let mut encoder = encoder_builder()
+            .color_encoding(preset)
+            .uses_original_profile(true)
+            .build()?;
+        let result: EncoderResult<u8> =
+            encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+        let icc = decoder.decode(&result)?.0.icc_profile;
+        let icc = icc.expect("ICC profile not retrieved");
+        lcms2::Profile::new_icc(&icc)?;
+        profiles.push(icc);
+    }
+
+    // Every preset describes a different color space
+    for (i, profile) in profiles.iter().enumerate() {
+        assert!(profiles[i + 1..].iter().all(|p| p != profile));
+    }
+
+    Ok(())
+}
+
 #[test]
 fn initial_buffer() -> TestResult {
     let mut encoder = encoder_builder().init_buffer_size(0).build()?;
     let sample = get_sample().to_rgb8();
-    let _: EncoderResult<u16> = encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+    let res: EncoderResult<u16> =
+        encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
+    // The output grew many times from the initial 32 bytes
+    decoder_builder().build()?.decode(&res)?;
     let _: EncoderResult<f16> = encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
     let _: EncoderResult<f32> = encoder.encode(sample.as_raw(), sample.width(), sample.height())?;
     Ok(())
@@ -0,0 +1,225 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Lossless conversion between JPEG and JPEG XL, and re-encoding of JPEG XL images
+
+use jpegxl_sys::types::JxlBool;
+
+use crate::{
+    decode::{Data, Metadata as DecodedMetadata},
+    decoder_builder,
+    encode::{EncoderFrame, JxlEncoder, Metadata},
+    encoder_builder, normalize_u16, quantize_f32,
+    utils::container_boxes,
+    DecodeError, Dithering, EncodeError, Error,
+};
+
+/// Boxes written by the encoder itself, which are not copied by [`reencode`]
+const STRUCTURAL_BOXES: [&[u8; 4]; 7] = [
+    b"JXL ", b"ftyp", b"jxlc", b"jxlp", b"jxll", b"jxli", b"jbrd",
+];
+
+/// Losslessly recompress a JPEG file to JPEG XL, keeping the data to reconstruct the
+/// original file bit for bit with [`reconstruct_jpeg`]
+///
+/// The output uses the container format, which the reconstruction data and the Exif and
+/// XMP metadata of the JPEG are stored in, and keeps the original color profile.
+///
+/// # Errors
+/// Return an [`EncodeError`] if the input is not a JPEG file libjxl can recompress
+pub fn transcode_jpeg_to_jxl(jpeg: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    let mut encoder = encoder_builder()
+        .use_container(true)
+        .uses_original_profile(true)
+        .build()?;
+    Ok(encoder.encode_jpeg(jpeg)?.data)
+}
+
+/// Reconstruct the original JPEG file from a JPEG XL image created by
+/// [`transcode_jpeg_to_jxl`] or `cjxl`
+///
+/// # Errors
+/// Return [`DecodeError::NoJpegReconstruction`] if the image was not recompressed from a
+/// JPEG file, or another [`DecodeError`] when the internal decoder fails
+pub fn reconstruct_jpeg(jxl: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    match decoder_builder().build()?.reconstruct(jxl)?.1 {
+        Data::Jpeg(jpeg) => Ok(jpeg),
+        Data::Pixels(_) => Err(DecodeError::NoJpegReconstruction),
+    }
+}
+
+/// Decode a JPEG XL image and encode its pixels again with `encoder`, e.g. to recompress
+/// with other settings, copying its metadata boxes verbatim
+///
+/// The color profile, alpha channel and bit depth of the image are kept, overriding
+/// `color_encoding`, `icc_profile` and `has_alpha` of `encoder`. Exif, XMP and unknown
+/// boxes are copied as is, so Brotli-compressed boxes stay compressed. The pixels are
+/// re-oriented upright, which the original orientation in Exif does not reflect.
+///
+/// # Errors
+/// Return [`Error::Decode`] if `data` is not a valid image, or [`Error::Encode`] with
+/// [`EncodeError::NotSupported`] for animations, which are not supported yet, or if the
+/// encoder fails
+pub fn reencode(data: &[u8], encoder: &mut JxlEncoder) -> Result<Vec<u8>, Error> {
+    let mut decoder = decoder_builder().icc_profile(true).build()?;
+    let info = decoder.read_basic_info(data)?;
+    if info.have_animation == JxlBool::True {
+        return Err(EncodeError::NotSupported.into());
+    }
+
+    // The pixels are decoded once, to the sample type chosen from the basic information
+    let integer = info.exponent_bits_per_sample == 0 && info.bits_per_sample <= 16;
+    let result = if integer {
+        let (metadata, pixels) = decoder.decode_with::<u16>(data)?;
+        // Samples are returned scaled to 16 bits
+        let pixels = quantize_f32(
+            &normalize_u16(&pixels, 16),
+            metadata.bits_per_sample,
+            Dithering::None,
+        );
+        let num_channels = prepare_encoder(data, &metadata, encoder)?;
+        let frame = EncoderFrame::new(&pixels).num_channels(num_channels);
+        encoder
+            .encode_frame_with_bit_depth::<u16, u16>(
+                &frame,
+                metadata.width,
+                metadata.height,
+                metadata.bits_per_sample,
+            )?
+            .data
+    } else {
+        let (metadata, pixels) = decoder.decode_with::<f32>(data)?;
+        let num_channels = prepare_encoder(data, &metadata, encoder)?;
+        let frame = EncoderFrame::new(&pixels).num_channels(num_channels);
+        encoder
+            .encode_frame::<f32, f32>(&frame, metadata.width, metadata.height)?
+            .data
+    };
+    Ok(result)
+}
+
+/// Set up `encoder` for the decoded image and copy its boxes, returning the number of
+/// channels of the pixels
+fn prepare_encoder(
+    data: &[u8],
+    metadata: &DecodedMetadata,
+    encoder: &mut JxlEncoder,
+) -> Result<u32, Error> {
+    encoder.has_alpha = metadata.has_alpha_channel;
+    encoder.icc_profile.clone_from(&metadata.icc_profile);
+    copy_boxes(data, encoder)?;
+    Ok(metadata.num_color_channels + u32::from(metadata.has_alpha_channel))
+}
+
+fn copy_boxes(data: &[u8], encoder: &mut JxlEncoder) -> Result<(), Error> {
+    // A bare codestream has no boxes to copy
+    if !data.starts_with(b"\0\0\0\x0cJXL ") {
+        return Ok(());
+    }
+    for b in container_boxes(data) {
+        let b = b?;
+        if !STRUCTURAL_BOXES.contains(&&b.box_type) {
+            encoder.add_metadata(&Metadata::Custom(b.box_type, b.payload), false)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        tests::{SAMPLE_JPEG, SAMPLE_JXL},
+        utils::ContainerBox,
+    };
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_roundtrip() -> TestResult {
+        let jxl = transcode_jpeg_to_jxl(SAMPLE_JPEG)?;
+        assert_eq!(reconstruct_jpeg(&jxl)?, SAMPLE_JPEG);
+
+        assert!(transcode_jpeg_to_jxl(&[0xff, 0xd8, 0x00]).is_err());
+        assert!(matches!(
+            reconstruct_jpeg(SAMPLE_JXL),
+            Err(DecodeError::NoJpegReconstruction)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_reencode() -> TestResult {
+        let pixels: Vec<u16> = (0..16 * 16 * 4).map(|i| i * 4).collect();
+        let mut encoder = encoder_builder()
+            .has_alpha(true)
+            .lossless(true)
+            .uses_original_profile(true)
+            .build()?;
+        encoder.add_metadata(&Metadata::Exif(&[0, 0, 0, 0, 1, 2]), false)?;
+        encoder.add_metadata(&Metadata::Xmp(b"<xmp/>"), true)?;
+        encoder.add_metadata(&Metadata::Custom(*b"abcd", b"custom"), false)?;
+        let original = encoder
+            .encode_frame_with_bit_depth::<u16, u16>(
+                &EncoderFrame::new(&pixels).num_channels(4),
+                16,
+                16,
+                14,
+            )?
+            .data;
+
+        // Boxes are kept verbatim, without the ones of the encoder
+        let metadata = |data: &[u8]| -> Result<Vec<(_, Vec<u8>)>, DecodeError> {
+            container_boxes(data)
+                .filter(|b| {
+                    b.as_ref()
+                        .map_or(true, |b| !STRUCTURAL_BOXES.contains(&&b.box_type))
+                })
+                .map(|b| {
+                    b.map(
+                        |ContainerBox {
+                             box_type, payload, ..
+                         }| (box_type, payload.to_vec()),
+                    )
+                })
+                .collect()
+        };
+        let mut encoder = encoder_builder()
+            .lossless(true)
+            .uses_original_profile(true)
+            .build()?;
+        let reencoded = reencode(&original, &mut encoder)?;
+        assert_eq!(metadata(&reencoded)?, metadata(&original)?);
+        assert_eq!(metadata(&reencoded)?.len(), 3);
+
+        let decoder = decoder_builder().build()?;
+        let (info, pixels) = decoder.decode_with::<u16>(&reencoded)?;
+        assert_eq!(info.bits_per_sample, 14);
+        assert!(info.has_alpha_channel);
+        assert_eq!(pixels, decoder.decode_with::<u16>(&original)?.1);
+
+        // The encoder is ready for the next image, a bare codestream without boxes
+        let reencoded = reencode(SAMPLE_JXL, &mut encoder)?;
+        assert!(metadata(&reencoded)?.is_empty());
+
+        Ok(())
+    }
+}
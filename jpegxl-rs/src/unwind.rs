@@ -0,0 +1,175 @@
+/*
+ * This file is part of jpegxl-rs.
+ *
+ * jpegxl-rs is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * jpegxl-rs is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Wrapper keeping panics of custom callbacks from unwinding into libjxl
+
+use std::{
+    ffi::c_void,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr::null_mut,
+};
+
+use jpegxl_sys::parallel_runner::JxlParallelRetCode;
+
+use crate::{
+    decode::BasicInfo,
+    memory::{AllocFn, FreeFn, MemoryManager},
+    parallel::{InitFn, JxlParallelRunner, RunFn, RunnerFn},
+};
+
+/// `JXL_PARALLEL_RET_RUNNER_ERROR`, a failure of the runner itself
+const RUNNER_ERROR: JxlParallelRetCode = -1;
+
+/// Wrapper around a [`MemoryManager`] or [`JxlParallelRunner`] catching the panics of its
+/// callbacks before they reach libjxl
+///
+/// libjxl is C++, and a panic unwinding through it may leave the decoder or encoder in an
+/// inconsistent state. With this wrapper, a panicking allocation fails as out of memory, a
+/// panicking free leaks the memory, and a panicking runner reports a runner error, all of
+/// which libjxl turns into a [`DecodeError`](crate::DecodeError) or
+/// [`EncodeError`](crate::EncodeError). Hosts loading untrusted plugins should wrap them.
+///
+/// # Example
+/// ```
+/// # use jpegxl_rs::{decoder_builder, memory::RustAllocator, unwind::CatchUnwind};
+/// let mm = CatchUnwind(RustAllocator);
+/// let decoder = decoder_builder().memory_manager(&mm).build().unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CatchUnwind<T>(pub T);
+
+impl<M: MemoryManager> MemoryManager for CatchUnwind<M> {
+    fn alloc(&self) -> AllocFn {
+        unsafe extern "C-unwind" fn alloc_fn<M: MemoryManager>(
+            opaque: *mut c_void,
+            size: usize,
+        ) -> *mut c_void {
+            let inner = (*opaque.cast::<CatchUnwind<M>>()).0.manager();
+            catch_unwind(|| unsafe { (inner.alloc)(inner.opaque, size) }).unwrap_or(null_mut())
+        }
+
+        alloc_fn::<M>
+    }
+
+    fn free(&self) -> FreeFn {
+        unsafe extern "C-unwind" fn free_fn<M: MemoryManager>(
+            opaque: *mut c_void,
+            address: *mut c_void,
+        ) {
+            let inner = (*opaque.cast::<CatchUnwind<M>>()).0.manager();
+            let _ = catch_unwind(|| unsafe { (inner.free)(inner.opaque, address) });
+        }
+
+        free_fn::<M>
+    }
+}
+
+impl<R: JxlParallelRunner> JxlParallelRunner for CatchUnwind<R> {
+    fn runner(&self) -> RunnerFn {
+        unsafe extern "C-unwind" fn runner_fn<R: JxlParallelRunner>(
+            runner_opaque: *mut c_void,
+            jpegxl_opaque: *mut c_void,
+            init_func: InitFn,
+            run_func: RunFn,
+            start_range: u32,
+            end_range: u32,
+        ) -> JxlParallelRetCode {
+            let inner = &(*runner_opaque.cast::<CatchUnwind<R>>()).0;
+            catch_unwind(AssertUnwindSafe(|| unsafe {
+                (inner.runner())(
+                    inner.as_opaque_ptr(),
+                    jpegxl_opaque,
+                    init_func,
+                    run_func,
+                    start_range,
+                    end_range,
+                )
+            }))
+            .unwrap_or(RUNNER_ERROR)
+        }
+
+        runner_fn::<R>
+    }
+
+    fn as_opaque_ptr(&self) -> *mut c_void {
+        (self as *const Self).cast_mut().cast()
+    }
+
+    fn callback_basic_info(&self, basic_info: &BasicInfo) {
+        // Called from Rust, so the panic stays on this side
+        self.0.callback_basic_info(basic_info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        decoder_builder, encoder_builder, memory::tests::PanicManager, tests::SAMPLE_JXL,
+        DecodeError, EncodeError,
+    };
+
+    struct PanicRunner;
+
+    impl JxlParallelRunner for PanicRunner {
+        fn runner(&self) -> RunnerFn {
+            #[cfg_attr(coverage_nightly, coverage(off))]
+            unsafe extern "C-unwind" fn runner(
+                _runner_opaque: *mut c_void,
+                _jpegxl_opaque: *mut c_void,
+                _init_func: InitFn,
+                _run_func: RunFn,
+                _start_range: u32,
+                _end_range: u32,
+            ) -> JxlParallelRetCode {
+                panic!("Runner panic test")
+            }
+
+            runner
+        }
+
+        fn as_opaque_ptr(&self) -> *mut c_void {
+            null_mut()
+        }
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_memory_manager() {
+        let mm = CatchUnwind(PanicManager {});
+        assert!(matches!(
+            decoder_builder().memory_manager(&mm).build(),
+            Err(DecodeError::CannotCreateDecoder)
+        ));
+        assert!(matches!(
+            encoder_builder().memory_manager(&mm).build(),
+            Err(EncodeError::CannotCreateEncoder)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    fn test_runner() -> TestResult {
+        let runner = CatchUnwind(PanicRunner);
+        let decoder = decoder_builder().parallel_runner(&runner).build()?;
+        assert!(decoder.decode(SAMPLE_JXL).is_err());
+
+        Ok(())
+    }
+}
@@ -17,7 +17,17 @@ along with jpegxl-rs.  If not, see <https://www.gnu.org/licenses/>.
 
 //! Utils functions when a decoder or encoder is not needed
 
-use jpegxl_sys::decode::{JxlSignature, JxlSignatureCheck};
+use std::{fmt, ptr::null};
+
+use jpegxl_sys::{
+    decode::{JxlDecoderVersion, JxlSignature, JxlSignatureCheck},
+    encode::{
+        JxlEncoderAddBox, JxlEncoderCreate, JxlEncoderDestroy, JxlEncoderSetCodestreamLevel,
+        JxlEncoderStatus, JxlEncoderUseBoxes, JxlEncoderVersion,
+    },
+};
+
+use crate::{encode::Metadata, DecodeError};
 
 /// Check if the signature of the input is valid.
 /// Return `None` if it needs more data.
@@ -32,10 +42,206 @@ pub fn check_valid_signature(buf: &[u8]) -> Option<bool> {
     }
 }
 
+/// Box in the JPEG XL container
+///
+/// With the `serde` feature, it serializes to its type as a string and size, without the
+/// payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ContainerBox<'a> {
+    /// Four character box type, e.g. `b"Exif"`
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_box_type"))]
+    pub box_type: [u8; 4],
+    /// Size of the whole box, header included
+    pub size: u64,
+    /// Raw box content without the header, still compressed for `brob` boxes
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub payload: &'a [u8],
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_box_type<S: serde::Serializer>(
+    box_type: &[u8; 4],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(box_type))
+}
+
+/// Iterator over the boxes of a JPEG XL container, see [`container_boxes`]
+#[derive(Clone, Debug)]
+pub struct ContainerBoxes<'a> {
+    rest: &'a [u8],
+    is_container: bool,
+    failed: bool,
+}
+
+impl<'a> Iterator for ContainerBoxes<'a> {
+    type Item = Result<ContainerBox<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.rest.is_empty() {
+            return None;
+        }
+
+        let item = self
+            .is_container
+            .then(|| self.next_box())
+            .flatten()
+            .ok_or(DecodeError::InvalidInput);
+        self.failed = item.is_err();
+        Some(item)
+    }
+}
+
+impl<'a> ContainerBoxes<'a> {
+    fn next_box(&mut self) -> Option<ContainerBox<'a>> {
+        let buf = self.rest;
+        let (box_type, header_len, size) = box_header(buf)?;
+        let len = usize::try_from(size).ok()?;
+        let payload = buf.get(header_len..len)?;
+        self.rest = &buf[len..];
+
+        Some(ContainerBox {
+            box_type,
+            size,
+            payload,
+        })
+    }
+}
+
+/// Type, header length and size of the box starting `buf`, whose content may be truncated.
+/// Return `None` if the header is truncated or the size smaller than the header
+pub(crate) fn box_header(buf: &[u8]) -> Option<([u8; 4], usize, u64)> {
+    let box_type = buf.get(4..8)?.try_into().ok()?;
+    let (header_len, size) = match u32::from_be_bytes(buf.get(..4)?.try_into().ok()?) {
+        // Box extends to the end of the file
+        0 => (8, buf.len() as u64),
+        1 => (16, u64::from_be_bytes(buf.get(8..16)?.try_into().ok()?)),
+        size => (8, u64::from(size)),
+    };
+    (size >= header_len as u64).then_some((box_type, header_len, size))
+}
+
+/// Iterate over all the boxes of a JPEG XL container, including unknown ones.
+///
+/// A bare codestream has no boxes, so a single [`DecodeError::InvalidInput`] is
+/// returned for it, as well as for truncated or malformed boxes, after which the iteration stops.
+#[must_use]
+pub fn container_boxes(buf: &[u8]) -> ContainerBoxes<'_> {
+    ContainerBoxes {
+        rest: buf,
+        is_container: unsafe { JxlSignatureCheck(buf.as_ptr(), buf.len()) }
+            == JxlSignature::Container,
+        failed: false,
+    }
+}
+
+/// Version of libjxl
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Major version
+    pub major: u32,
+    /// Minor version
+    pub minor: u32,
+    /// Patch version
+    pub patch: u32,
+}
+
+impl Version {
+    /// Split a version as returned by libjxl, `major * 1000000 + minor * 1000 + patch`
+    #[must_use]
+    pub const fn from_encoded(version: u32) -> Self {
+        Self {
+            major: version / 1_000_000,
+            minor: version / 1000 % 1000,
+            patch: version % 1000,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Version of the linked libjxl decoder, which can differ from the one this crate was
+/// built against when libjxl is linked dynamically
+#[must_use]
+pub fn runtime_version() -> Version {
+    Version::from_encoded(unsafe { JxlDecoderVersion() })
+}
+
+/// Features of the linked libjxl, see [`capabilities`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    /// Version of the decoder library
+    pub decoder_version: Version,
+    /// Version of the encoder library
+    pub encoder_version: Version,
+    /// Highest codestream level the encoder can target, 5 or 10
+    pub max_codestream_level: u32,
+    /// Number of worker threads of a default `ThreadsRunner`, or 0 without the `threads`
+    /// feature
+    pub threads: usize,
+    /// Whether metadata boxes can be added to encoded images
+    pub boxes: bool,
+    /// Whether metadata boxes can be compressed with Brotli
+    pub compressed_boxes: bool,
+}
+
+/// Query the features of the linked libjxl, by probing a temporary encoder
+///
+/// # Example
+/// ```
+/// let capabilities = jpegxl_rs::utils::capabilities();
+/// assert!(capabilities.decoder_version >= jpegxl_rs::utils::Version::from_encoded(10000));
+/// ```
+#[must_use]
+pub fn capabilities() -> Capabilities {
+    let (mut max_codestream_level, mut boxes, mut compressed_boxes) = (5, false, false);
+    unsafe {
+        let enc = JxlEncoderCreate(null());
+        if !enc.is_null() {
+            if JxlEncoderSetCodestreamLevel(enc, 10) == JxlEncoderStatus::Success {
+                max_codestream_level = 10;
+            }
+            boxes = JxlEncoderUseBoxes(enc) == JxlEncoderStatus::Success;
+            compressed_boxes = boxes
+                && JxlEncoderAddBox(
+                    enc,
+                    &Metadata::box_type(*b"test"),
+                    [0u8].as_ptr(),
+                    1,
+                    true.into(),
+                ) == JxlEncoderStatus::Success;
+            JxlEncoderDestroy(enc);
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    let threads = unsafe {
+        jpegxl_sys::thread_parallel_runner::JxlThreadParallelRunnerDefaultNumWorkerThreads()
+    };
+    #[cfg(not(feature = "threads"))]
+    let threads = 0;
+
+    Capabilities {
+        decoder_version: runtime_version(),
+        encoder_version: Version::from_encoded(unsafe { JxlEncoderVersion() }),
+        max_codestream_level,
+        threads,
+        boxes,
+        compressed_boxes,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tests::SAMPLE_JXL;
+    use crate::tests::{SAMPLE_JXL, SAMPLE_JXL_JPEG};
 
     use pretty_assertions::assert_eq;
 
@@ -45,4 +251,53 @@ mod tests {
         assert_eq!(check_valid_signature(&[0; 64]), Some(false));
         assert_eq!(check_valid_signature(SAMPLE_JXL), Some(true));
     }
+
+    #[test]
+    fn test_container_boxes() {
+        let boxes = container_boxes(SAMPLE_JXL_JPEG)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Failed to read boxes");
+        assert_eq!(boxes[0].box_type, *b"JXL ");
+        assert_eq!(boxes[0].payload, [0xD, 0xA, 0x87, 0xA]);
+        assert_eq!(boxes[1].box_type, *b"ftyp");
+        assert_eq!(
+            boxes.iter().map(|b| b.size).sum::<u64>(),
+            SAMPLE_JXL_JPEG.len() as u64
+        );
+
+        let truncated = &SAMPLE_JXL_JPEG[..SAMPLE_JXL_JPEG.len() - 1];
+        assert!(container_boxes(truncated)
+            .last()
+            .is_some_and(|b| b.is_err()));
+        assert!(matches!(
+            container_boxes(SAMPLE_JXL).collect::<Vec<_>>()[..],
+            [Err(DecodeError::InvalidInput)]
+        ));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let version = Version::from_encoded(10003);
+        assert_eq!(
+            version,
+            Version {
+                major: 0,
+                minor: 10,
+                patch: 3
+            }
+        );
+        assert_eq!(version.to_string(), "0.10.3");
+
+        // Any libjxl with the API of the bindings
+        let version = runtime_version();
+        assert_eq!((version.major, version.minor), (0, 10));
+
+        let capabilities = capabilities();
+        assert_eq!(capabilities.encoder_version, version);
+        assert_eq!(capabilities.max_codestream_level, 10);
+        assert!(capabilities.boxes);
+        assert!(capabilities.compressed_boxes);
+        #[cfg(feature = "threads")]
+        assert!(capabilities.threads > 0);
+    }
 }
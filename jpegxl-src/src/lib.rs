@@ -52,6 +52,19 @@ pub fn build() {
         config.env("CMAKE_BUILD_PARALLEL_LEVEL", format!("{p}"));
     }
 
+    // Build scripts run on the host, so look at the target through cargo's variables
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_vendor = env::var("CARGO_CFG_TARGET_VENDOR").unwrap_or_default();
+
+    if target_os == "wasi" {
+        if let Ok(sdk) = env::var("WASI_SDK_PATH") {
+            config.define(
+                "CMAKE_TOOLCHAIN_FILE",
+                Path::new(&sdk).join("share/cmake/wasi-sdk.cmake"),
+            );
+        }
+    }
+
     #[cfg(target_os = "windows")]
     {
         config
@@ -82,13 +95,19 @@ pub fn build() {
     println!("cargo:rustc-link-lib=static=brotlicommon");
     println!("cargo:rustc-link-lib=static=brotlidec");
     println!("cargo:rustc-link-lib=static=brotlienc");
-    #[cfg(any(target_vendor = "apple", target_os = "freebsd"))]
-    {
-        println!("cargo:rustc-link-lib=c++");
+    for lib in cxx_runtime(&target_os, &target_vendor) {
+        println!("cargo:rustc-link-lib={lib}");
     }
-    #[cfg(target_os = "linux")]
-    {
-        println!("cargo:rustc-link-lib=stdc++");
+}
+
+/// C++ runtime libraries to link for the target.
+/// Emscripten links its own runtime, and Windows gets it from the MSVC toolchain
+fn cxx_runtime(target_os: &str, target_vendor: &str) -> &'static [&'static str] {
+    match (target_os, target_vendor) {
+        (_, "apple") | ("freebsd", _) => &["c++"],
+        ("linux", _) => &["stdc++"],
+        ("wasi", _) => &["c++", "c++abi"],
+        _ => &[],
     }
 }
 
@@ -96,6 +115,14 @@ pub fn build() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cxx_runtime() {
+        assert_eq!(cxx_runtime("macos", "apple"), ["c++"]);
+        assert_eq!(cxx_runtime("linux", "unknown"), ["stdc++"]);
+        assert_eq!(cxx_runtime("wasi", "unknown"), ["c++", "c++abi"]);
+        assert!(cxx_runtime("emscripten", "unknown").is_empty());
+    }
+
     #[test]
     #[cfg_attr(coverage_nightly, coverage(off))]
     fn test_source_dir() {
@@ -30,6 +30,7 @@ fn main() {
         if let Ok(path) = env::var("DEP_JXL_LIB") {
             println!("cargo:rustc-link-search=native={path}");
             println!("cargo:rustc-link-lib=jxl");
+            println!("cargo:rustc-link-lib=jxl_cms");
             #[cfg(feature = "threads")]
             println!("cargo:rustc-link-lib=jxl_threads");
         } else {
@@ -37,6 +38,10 @@ fn main() {
                 .atleast_version(version)
                 .probe("libjxl")
                 .unwrap_or_else(|_| panic!("Cannot find `libjxl` with version >= {version}"));
+            pkg_config::Config::new()
+                .atleast_version(version)
+                .probe("libjxl_cms")
+                .unwrap_or_else(|_| panic!("Cannot find `libjxl_cms` with version >= {version}"));
             #[cfg(feature = "threads")]
             pkg_config::Config::new()
                 .atleast_version(version)
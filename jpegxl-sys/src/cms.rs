@@ -34,8 +34,8 @@ pub type JpegXlCmsSetFieldsFromIccFunc = extern "C" fn(
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct JxlColorProfileIcc {
-    data: *const u8,
-    size: usize,
+    pub data: *const u8,
+    pub size: usize,
 }
 
 #[repr(C)]
@@ -2,6 +2,7 @@ use crate::types::JxlBool;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum JxlOrientation {
     Identity = 1,
     FlipHorizontal = 2,
@@ -15,6 +16,7 @@ pub enum JxlOrientation {
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum JxlExtraChannelType {
     Alpha,
     Depth,
@@ -37,6 +39,7 @@ pub enum JxlExtraChannelType {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlPreviewHeader {
     pub xsize: u32,
     pub ysize: u32,
@@ -44,6 +47,7 @@ pub struct JxlPreviewHeader {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlAnimationHeader {
     pub tps_numerator: u32,
     pub tps_denominator: u32,
@@ -53,6 +57,7 @@ pub struct JxlAnimationHeader {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlBasicInfo {
     pub have_container: JxlBool,
     pub xsize: u32,
@@ -76,11 +81,13 @@ pub struct JxlBasicInfo {
     pub animation: JxlAnimationHeader,
     pub intrinsic_xsize: u32,
     pub intrinsic_ysize: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
     _padding: [u8; 100],
 }
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlExtraChannelInfo {
     pub type_: JxlExtraChannelType,
     pub bits_per_sample: u32,
@@ -94,12 +101,14 @@ pub struct JxlExtraChannelInfo {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlHeaderExtensions {
     pub extensions: u64,
 }
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum JxlBlendMode {
     Replace = 0,
     Add = 1,
@@ -110,6 +119,7 @@ pub enum JxlBlendMode {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlBlendInfo {
     pub blendmode: JxlBlendMode,
     pub source: u32,
@@ -119,6 +129,7 @@ pub struct JxlBlendInfo {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlLayerInfo {
     pub have_crop: JxlBool,
     pub crop_x0: i32,
@@ -131,6 +142,7 @@ pub struct JxlLayerInfo {
 
 #[repr(C)]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct JxlFrameHeader {
     pub duration: u32,
     pub timecode: u32,
@@ -17,6 +17,16 @@ along with jpegxl-sys.  If not, see <https://www.gnu.org/licenses/>.
 
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
+#[cfg(all(
+    feature = "threads",
+    target_family = "wasm",
+    not(target_feature = "atomics")
+))]
+compile_error!(
+    "The `threads` feature needs a WebAssembly target with atomics, \
+     disable the default features to build without it"
+);
+
 pub mod cms;
 pub mod codestream_header;
 pub mod color_encoding;
@@ -89,6 +99,25 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_endianness() {
+        assert_eq!(JxlEndianness::try_from(2), Ok(JxlEndianness::Big));
+        assert_eq!(JxlEndianness::try_from(3), Err(3));
+
+        let native = JxlEndianness::Native.resolve();
+        assert_ne!(native, JxlEndianness::Native);
+        assert_eq!(
+            native == JxlEndianness::Little,
+            JxlEndianness::native_is_little()
+        );
+        assert!(!JxlEndianness::Native.needs_swap());
+        assert!(!native.needs_swap());
+        assert_ne!(
+            JxlEndianness::Little.needs_swap(),
+            JxlEndianness::Big.needs_swap()
+        );
+    }
+
     #[cfg_attr(coverage_nightly, coverage(off))]
     unsafe fn decode(decoder: *mut JxlDecoder, sample: &[u8]) {
         use JxlDecoderStatus::{
@@ -164,7 +193,7 @@ mod test {
                     jxl_dec_assert!(status, "SetBuffer");
                 }
 
-                FullImage => continue,
+                FullImage => {}
                 Success => {
                     assert_eq!(buffer.len(), (x_size * y_size * 3) as usize);
                     return;
@@ -311,7 +340,7 @@ mod test {
                         jxl_dec_assert!(status, "SetBuffer");
                     }
 
-                    FullImage => continue,
+                    FullImage => {}
                     Success => {
                         assert_eq!(buffer.len(), (x_size * y_size * 3) as usize);
                         break;
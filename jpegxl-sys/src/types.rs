@@ -19,6 +19,7 @@ use std::ffi::c_char;
 
 #[repr(i32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum JxlBool {
     True = 1,
     False = 0,
@@ -51,6 +52,46 @@ pub enum JxlEndianness {
     Big,
 }
 
+impl TryFrom<u32> for JxlEndianness {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Native),
+            1 => Ok(Self::Little),
+            2 => Ok(Self::Big),
+            v => Err(v),
+        }
+    }
+}
+
+impl JxlEndianness {
+    /// Whether the target stores multibyte values in little endian order
+    #[must_use]
+    pub const fn native_is_little() -> bool {
+        cfg!(target_endian = "little")
+    }
+
+    /// Resolve [`Self::Native`] to the byte order of the target
+    #[must_use]
+    pub const fn resolve(self) -> Self {
+        match self {
+            Self::Native if Self::native_is_little() => Self::Little,
+            Self::Native => Self::Big,
+            e => e,
+        }
+    }
+
+    /// Whether the bytes of multibyte values must be swapped to read them natively
+    #[must_use]
+    pub const fn needs_swap(self) -> bool {
+        matches!(
+            (self.resolve(), Self::native_is_little()),
+            (Self::Big, true) | (Self::Little, false)
+        )
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct JxlPixelFormat {
@@ -71,9 +112,9 @@ pub enum JxlBitDepthType {
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct JxlBitDepth {
-    type_: JxlBitDepthType,
-    bits_per_sample: u32,
-    exponent_bits_per_sample: u32,
+    pub type_: JxlBitDepthType,
+    pub bits_per_sample: u32,
+    pub exponent_bits_per_sample: u32,
 }
 
 #[repr(transparent)]